@@ -0,0 +1,432 @@
+//! `heres`: manage the fee config and capsules from the command line.
+//!
+//! Every command prints a single JSON document on stdout, so the output can be piped into `jq` or parsed
+//! by scripts. Errors are printed as `{"error": ..., "logs": [...]}` with exit code 1. With `--dry-run`,
+//! transactions are simulated instead of sent and the simulation result is printed.
+
+mod render;
+
+use std::path::{Path, PathBuf};
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use heres_client::{
+    accounts, instructions, pda, DelegateCapsuleArgs, Intent, IntentCapsule, ScheduleExecuteIntentArgs,
+    LEGACY_MAX_BENEFICIARIES,
+};
+use serde_json::{json, Value};
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::{
+    client_error::ErrorKind as ClientErrorKind,
+    config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    filter::{Memcmp, RpcFilterType},
+    request::{RpcError, RpcResponseErrorData},
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+
+#[derive(Parser)]
+#[command(name = "heres", version, about = "Manage Heres capsules and the platform fee config")]
+struct Cli {
+    /// RPC endpoint; use the ER/TEE endpoint for delegated capsules (check-in, schedule)
+    #[arg(long, short = 'u', global = true, env = "HERES_RPC_URL", default_value = "https://api.devnet.solana.com")]
+    url: String,
+
+    /// Keypair file that signs and pays [default: ~/.config/solana/id.json]
+    #[arg(long, short = 'k', global = true, env = "HERES_KEYPAIR")]
+    keypair: Option<PathBuf>,
+
+    /// Simulate transactions instead of sending them
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Platform fee config
+    #[command(subcommand)]
+    Fee(FeeCommand),
+    /// Capsule of the keypair (or of --owner for read-only and keeper commands)
+    #[command(subcommand)]
+    Capsule(CapsuleCommand),
+    /// Delegate the keypair's capsule and vault to an ER validator
+    Delegate {
+        /// Validator to delegate to [default: TEE validator]
+        #[arg(long)]
+        validator: Option<Pubkey>,
+        /// Periodic ER -> L1 commits; 0 commits only on undelegation
+        #[arg(long, default_value_t = 0)]
+        commit_frequency_ms: u32,
+    },
+    /// Schedule the execute_intent crank for the keypair's delegated capsule (send to the ER endpoint)
+    Schedule {
+        #[arg(long)]
+        interval_ms: u64,
+        #[arg(long)]
+        iterations: u64,
+    },
+    /// List capsules owned by the program on this endpoint (delegated capsules only show up on the ER)
+    List {
+        /// Only capsules owned by this wallet
+        #[arg(long)]
+        owner: Option<Pubkey>,
+        /// Only capsules that have not been executed yet
+        #[arg(long)]
+        active: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FeeCommand {
+    /// Create the fee config with the keypair as authority
+    Init {
+        #[arg(long)]
+        fee_recipient: Pubkey,
+        #[arg(long)]
+        creation_fee_lamports: u64,
+        #[arg(long)]
+        execution_fee_bps: u16,
+    },
+    /// Print the fee config and program config
+    Show,
+    /// Change fees (authority only); increases wait for the fee update delay
+    Update {
+        #[arg(long)]
+        creation_fee_lamports: u64,
+        #[arg(long)]
+        execution_fee_bps: u16,
+    },
+}
+
+#[derive(Args)]
+struct OwnerArg {
+    /// Capsule owner [default: the keypair]
+    #[arg(long)]
+    owner: Option<Pubkey>,
+}
+
+#[derive(Subcommand)]
+enum CapsuleCommand {
+    /// Create a capsule locking the intent's totalAmount
+    Create {
+        /// Intent JSON file, as written by the web app
+        #[arg(long)]
+        intent: PathBuf,
+        /// Seconds without activity before the capsule can be executed
+        #[arg(long)]
+        inactivity_period: i64,
+        /// Lock SPL tokens of this mint instead of SOL
+        #[arg(long)]
+        mint: Option<Pubkey>,
+    },
+    /// Print a capsule and its intent
+    Show(OwnerArg),
+    /// Replace the intent of the keypair's capsule
+    UpdateIntent {
+        #[arg(long)]
+        intent: PathBuf,
+    },
+    /// Record owner activity, resetting the inactivity timer
+    CheckIn {
+        /// The capsule is delegated and --url is the ER endpoint; reschedules the crank
+        #[arg(long)]
+        er: bool,
+    },
+    /// Cancel the keypair's capsule and withdraw everything
+    Cancel,
+    /// Pay out an executed capsule to its beneficiaries (anyone can call; intents of up to 15 beneficiaries)
+    Distribute(OwnerArg),
+}
+
+struct Session {
+    rpc: RpcClient,
+    keypair: Option<PathBuf>,
+    dry_run: bool,
+}
+
+impl Session {
+    /// The keypair, read only by commands that sign, so read-only commands work without one
+    fn signer(&self) -> Result<Keypair> {
+        let path = match &self.keypair {
+            Some(path) => path.clone(),
+            None => default_keypair_path()?,
+        };
+        read_keypair_file(&path).map_err(|e| anyhow!("reading keypair {}: {}", path.display(), e))
+    }
+
+    fn me(&self) -> Result<Pubkey> {
+        Ok(self.signer()?.pubkey())
+    }
+
+    fn owner(&self, owner: &OwnerArg) -> Result<Pubkey> {
+        match owner.owner {
+            Some(owner) => Ok(owner),
+            None => self.me(),
+        }
+    }
+
+    fn account(&self, address: &Pubkey) -> Result<Option<solana_sdk::account::Account>> {
+        Ok(self.rpc.get_account_with_commitment(address, self.rpc.commitment())?.value)
+    }
+
+    fn capsule(&self, owner: &Pubkey) -> Result<(Pubkey, IntentCapsule, Vec<u8>, Pubkey)> {
+        let address = pda::capsule(owner).0;
+        let account = self
+            .account(&address)?
+            .ok_or_else(|| anyhow!("no capsule for owner {}", owner))?;
+        let capsule = accounts::decode_capsule(&account.data)?;
+        let intent = self.intent(&address, &capsule)?;
+        Ok((address, capsule, intent, account.owner))
+    }
+
+    fn intent(&self, capsule_address: &Pubkey, capsule: &IntentCapsule) -> Result<Vec<u8>> {
+        let intent_data = match self.account(&pda::intent_data(capsule_address).0)? {
+            Some(account) => Some(accounts::decode_intent_data(&account.data)?),
+            None => None,
+        };
+        Ok(accounts::capsule_intent(capsule, intent_data.as_ref()).to_vec())
+    }
+
+    fn fee_config(&self) -> Result<heres_client::FeeConfig> {
+        let data = self
+            .rpc
+            .get_account_data(&pda::fee_config().0)
+            .context("fee config not found; run `heres fee init` first")?;
+        Ok(accounts::decode_fee_config(&data)?)
+    }
+
+    /// Send (or simulate) a transaction paid and signed by the keypair
+    fn submit(&self, instructions: &[Instruction]) -> Result<Value> {
+        let signer = self.signer()?;
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(instructions, Some(&signer.pubkey()), &[&signer], blockhash);
+        if self.dry_run {
+            let result = self.rpc.simulate_transaction(&tx)?.value;
+            return Ok(json!({
+                "dryRun": true,
+                "err": result.err.map(|e| e.to_string()),
+                "unitsConsumed": result.units_consumed,
+                "logs": result.logs,
+            }));
+        }
+        match self.rpc.send_and_confirm_transaction(&tx) {
+            Ok(signature) => Ok(json!({ "signature": signature.to_string() })),
+            Err(e) => {
+                let logs = match e.kind() {
+                    ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                        data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+                        ..
+                    }) => result.logs.clone().unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+                Err(Failure { error: e.to_string(), logs }.into())
+            }
+        }
+    }
+}
+
+/// Failed transaction, printed with the program logs from preflight
+#[derive(Debug)]
+struct Failure {
+    error: String,
+    logs: Vec<String>,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.error)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Intent file contents. Only the shape is checked here: the program's validation runs in preflight
+/// (or with --dry-run) and its logs name the offending beneficiary.
+fn read_intent(path: &Path) -> Result<Vec<u8>> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    Intent::decode(&data).with_context(|| format!("{} is not an intent", path.display()))?;
+    Ok(data)
+}
+
+fn default_keypair_path() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set; pass --keypair"))?;
+    Ok(PathBuf::from(home).join(".config/solana/id.json"))
+}
+
+fn run(cli: Cli) -> Result<Value> {
+    let session = Session {
+        rpc: RpcClient::new_with_commitment(cli.url, CommitmentConfig::confirmed()),
+        keypair: cli.keypair,
+        dry_run: cli.dry_run,
+    };
+
+    match cli.command {
+        Command::Fee(FeeCommand::Init {
+            fee_recipient,
+            creation_fee_lamports,
+            execution_fee_bps,
+        }) => session.submit(&[instructions::init_fee_config(
+            &session.me()?,
+            &fee_recipient,
+            creation_fee_lamports,
+            execution_fee_bps,
+        )]),
+        Command::Fee(FeeCommand::Show) => {
+            let (fee_address, _) = pda::fee_config();
+            let (program_address, _) = pda::program_config();
+            let program_config = match session.account(&program_address)? {
+                Some(account) => render::program_config(&program_address, &accounts::decode_program_config(&account.data)?),
+                None => Value::Null,
+            };
+            Ok(json!({
+                "feeConfig": render::fee_config(&fee_address, &session.fee_config()?),
+                "programConfig": program_config,
+            }))
+        }
+        Command::Fee(FeeCommand::Update {
+            creation_fee_lamports,
+            execution_fee_bps,
+        }) => session.submit(&[instructions::update_fee_config(
+            &session.me()?,
+            creation_fee_lamports,
+            execution_fee_bps,
+        )]),
+        Command::Capsule(CapsuleCommand::Create {
+            intent,
+            inactivity_period,
+            mint,
+        }) => {
+            let fee_recipient = session.fee_config()?.fee_recipient;
+            session.submit(&[instructions::create_capsule(
+                &session.me()?,
+                Some(&fee_recipient),
+                mint.as_ref(),
+                inactivity_period,
+                read_intent(&intent)?,
+            )])
+        }
+        Command::Capsule(CapsuleCommand::Show(owner)) => {
+            let (address, capsule, intent, account_owner) = session.capsule(&session.owner(&owner)?)?;
+            Ok(render::capsule(&address, &account_owner, &capsule, &intent))
+        }
+        Command::Capsule(CapsuleCommand::UpdateIntent { intent }) => {
+            session.submit(&[instructions::update_intent(&session.me()?, read_intent(&intent)?)])
+        }
+        Command::Capsule(CapsuleCommand::CheckIn { er }) => {
+            session.submit(&[instructions::update_activity(&session.me()?, er)])
+        }
+        Command::Capsule(CapsuleCommand::Cancel) => {
+            let me = session.me()?;
+            let (_, capsule, _, _) = session.capsule(&me)?;
+            let mint = (capsule.mint != Pubkey::default()).then_some(capsule.mint);
+            session.submit(&[instructions::cancel_capsule(&me, mint.as_ref())])
+        }
+        Command::Capsule(CapsuleCommand::Distribute(owner)) => {
+            let owner = session.owner(&owner)?;
+            let (_, capsule, intent, _) = session.capsule(&owner)?;
+            if capsule.executed_at.is_none() {
+                bail!("capsule of {} has not been executed", owner);
+            }
+            let beneficiaries: Vec<Pubkey> = heres_program::parse_intent(&intent)
+                .map_err(|e| anyhow!("capsule intent cannot be parsed: {}", e))?
+                .beneficiaries
+                .iter()
+                .map(|b| b.address)
+                .collect();
+            // submit sends legacy transactions, which can't carry the accounts of a larger intent
+            if beneficiaries.len() > LEGACY_MAX_BENEFICIARIES {
+                bail!(
+                    "capsule of {} has {} beneficiaries; the CLI can distribute at most {} in a legacy transaction, \
+                     send distribute_assets in a v0 transaction with an address lookup table instead",
+                    owner,
+                    beneficiaries.len(),
+                    LEGACY_MAX_BENEFICIARIES
+                );
+            }
+            let mint = (capsule.mint != Pubkey::default()).then_some(capsule.mint);
+            let executor = (capsule.executed_by != Pubkey::default()).then_some(capsule.executed_by);
+            session.submit(&[instructions::distribute_assets(
+                &owner,
+                &session.me()?,
+                &session.fee_config()?.fee_recipient,
+                mint.as_ref(),
+                &beneficiaries,
+                executor.as_ref(),
+            )])
+        }
+        Command::Delegate {
+            validator,
+            commit_frequency_ms,
+        } => {
+            let me = session.me()?;
+            session.submit(&[instructions::delegate_capsule(
+                &me,
+                &me,
+                DelegateCapsuleArgs {
+                    commit_frequency_ms,
+                    validator,
+                },
+            )])
+        }
+        Command::Schedule {
+            interval_ms,
+            iterations,
+        } => session.submit(&[instructions::schedule_execute_intent(
+            &session.me()?,
+            ScheduleExecuteIntentArgs {
+                execution_interval_millis: interval_ms,
+                iterations,
+            },
+        )]),
+        Command::List { owner, active } => {
+            let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                0,
+                <IntentCapsule as anchor_lang::Discriminator>::DISCRIMINATOR,
+            ))];
+            if let Some(owner) = owner {
+                filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(8, owner.as_ref())));
+            }
+            let config = RpcProgramAccountsConfig {
+                filters: Some(filters),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            };
+            let mut capsules = Vec::new();
+            for (address, account) in session.rpc.get_program_accounts_with_config(&heres_program::ID, config)? {
+                let Ok(capsule) = accounts::decode_capsule(&account.data) else {
+                    continue;
+                };
+                if active && !capsule.is_active {
+                    continue;
+                }
+                let intent = session.intent(&address, &capsule)?;
+                capsules.push(render::capsule(&address, &account.owner, &capsule, &intent));
+            }
+            Ok(Value::Array(capsules))
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(output) => println!("{}", serde_json::to_string_pretty(&output).unwrap()),
+        Err(e) => {
+            let logs = e.downcast_ref::<Failure>().map(|f| f.logs.clone()).unwrap_or_default();
+            println!("{}", json!({ "error": format!("{:#}", e), "logs": logs }));
+            std::process::exit(1);
+        }
+    }
+}
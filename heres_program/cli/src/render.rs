@@ -0,0 +1,79 @@
+//! JSON views of program accounts. Pubkeys are base58 strings; unset optional pubkeys
+//! (Pubkey::default()) are null.
+
+use anchor_lang::prelude::Pubkey;
+use heres_client::{FeeConfig, Intent, IntentCapsule, ProgramConfig};
+use serde_json::{json, Value};
+
+fn optional(pubkey: &Pubkey) -> Value {
+    if *pubkey == Pubkey::default() {
+        Value::Null
+    } else {
+        json!(pubkey.to_string())
+    }
+}
+
+pub fn fee_config(address: &Pubkey, config: &FeeConfig) -> Value {
+    json!({
+        "address": address.to_string(),
+        "authority": config.authority.to_string(),
+        "feeRecipient": config.fee_recipient.to_string(),
+        "creationFeeLamports": config.creation_fee_lamports,
+        "executionFeeBps": config.execution_fee_bps,
+        "pendingAuthority": config.pending_authority.map(|a| a.to_string()),
+        "feeUpdateDelay": config.fee_update_delay,
+        "pendingCreationFeeLamports": config.pending_creation_fee_lamports,
+        "pendingExecutionFeeBps": config.pending_execution_fee_bps,
+        "pendingFeesEffectiveAt": config.pending_fees_effective_at,
+        "pendingFeeUpdateDelay": config.pending_fee_update_delay,
+        "pendingDelayEffectiveAt": config.pending_delay_effective_at,
+        "keeperBountyLamports": config.keeper_bounty_lamports,
+        "keeperBountyBps": config.keeper_bounty_bps,
+    })
+}
+
+pub fn program_config(address: &Pubkey, config: &ProgramConfig) -> Value {
+    json!({
+        "address": address.to_string(),
+        "authority": config.authority.to_string(),
+        "pauseCreate": config.pause_create,
+        "pauseExecute": config.pause_execute,
+        "pauseDistribute": config.pause_distribute,
+        "allowedValidators": config.allowed_validators.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+        "activityOracle": optional(&config.activity_oracle),
+    })
+}
+
+/// Capsule fields plus its decoded intent; an intent that is not valid JSON is shown as a string.
+/// `account_owner` is the account's program owner, so delegated capsules show up as such.
+pub fn capsule(address: &Pubkey, account_owner: &Pubkey, capsule: &IntentCapsule, intent: &[u8]) -> Value {
+    let intent = match Intent::decode(intent) {
+        Ok(intent) => serde_json::to_value(intent).unwrap_or(Value::Null),
+        Err(_) => json!(String::from_utf8_lossy(intent)),
+    };
+    json!({
+        "address": address.to_string(),
+        "owner": capsule.owner.to_string(),
+        "delegated": *account_owner != heres_program::ID,
+        "version": capsule.version,
+        "isActive": capsule.is_active,
+        "inactivityPeriod": capsule.inactivity_period,
+        "lastActivity": capsule.last_activity,
+        "executeAfter": capsule.last_activity.saturating_add(capsule.inactivity_period),
+        "executedAt": capsule.executed_at,
+        "executedBy": optional(&capsule.executed_by),
+        "distributedAt": capsule.distributed_at,
+        "mint": optional(&capsule.mint),
+        "lockedAmount": capsule.locked_amount,
+        "rentRecipient": optional(&capsule.rent_recipient),
+        "retryCount": capsule.retry_count,
+        "crankTaskId": capsule.crank_task_id,
+        "crankIntervalMillis": capsule.crank_interval_millis,
+        "crankIterations": capsule.crank_iterations,
+        "delegatedValidator": optional(&capsule.delegated_validator),
+        "pendingValidator": optional(&capsule.pending_validator),
+        "commitFrequencyMs": capsule.commit_frequency_ms,
+        "activityNonce": capsule.activity_nonce,
+        "intent": intent,
+    })
+}
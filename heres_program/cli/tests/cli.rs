@@ -0,0 +1,64 @@
+use std::process::Command;
+
+use solana_sdk::signature::{write_keypair_file, Keypair};
+
+fn heres(args: &[&str]) -> (bool, serde_json::Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_heres")).args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.success(), serde_json::from_str(&stdout).unwrap())
+}
+
+#[test]
+fn errors_are_printed_as_json() {
+    let (ok, output) = heres(&[
+        "--keypair",
+        "/nonexistent/id.json",
+        "fee",
+        "update",
+        "--creation-fee-lamports",
+        "0",
+        "--execution-fee-bps",
+        "0",
+    ]);
+    assert!(!ok);
+    assert!(output["error"].as_str().unwrap().contains("/nonexistent/id.json"));
+}
+
+#[test]
+fn read_only_commands_do_not_need_a_keypair() {
+    // No RPC is reachable, so these fail on the network instead of on the missing keypair
+    let owner = "11111111111111111111111111111111";
+    for command in [&["fee", "show"][..], &["capsule", "show", "--owner", owner], &["list"]] {
+        let mut args = vec!["--url", "http://127.0.0.1:1", "--keypair", "/nonexistent/id.json"];
+        args.extend_from_slice(command);
+        let (ok, output) = heres(&args);
+        assert!(!ok);
+        assert!(!output["error"].as_str().unwrap().contains("keypair"), "{:?}: {}", command, output);
+    }
+}
+
+#[test]
+fn malformed_intent_file_is_rejected_before_sending() {
+    let dir = std::env::temp_dir().join(format!("heres-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let keypair = dir.join("id.json");
+    write_keypair_file(&Keypair::new(), &keypair).unwrap();
+    let intent = dir.join("intent.json");
+    // No RPC is reachable, so a network error would mean the intent got as far as being sent
+    std::fs::write(&intent, r#"{"intent":"","beneficiaries":[{"address":"not a pubkey"}]}"#).unwrap();
+
+    let (ok, output) = heres(&[
+        "--url",
+        "http://127.0.0.1:1",
+        "--keypair",
+        keypair.to_str().unwrap(),
+        "--dry-run",
+        "capsule",
+        "update-intent",
+        "--intent",
+        intent.to_str().unwrap(),
+    ]);
+    assert!(!ok);
+    assert!(output["error"].as_str().unwrap().contains("is not an intent"), "{}", output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
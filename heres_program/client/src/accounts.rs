@@ -0,0 +1,45 @@
+//! Decoders for the program's accounts.
+
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use heres_program::{CapsuleVault, FeeConfig, IntentCapsule, IntentCapsuleV0, IntentData, ProgramConfig};
+
+pub use anchor_lang::error::Error;
+
+/// Decode an Anchor account (discriminator included)
+pub fn decode<T: AccountDeserialize>(data: &[u8]) -> Result<T, Error> {
+    T::try_deserialize(&mut &data[..])
+}
+
+/// Decode a capsule in any layout. v0 accounts (not yet run through migrate_capsule) are recognised by
+/// size, like the program does, and converted with `version` 0 and the intent still inline.
+pub fn decode_capsule(data: &[u8]) -> Result<IntentCapsule, Error> {
+    if data.len() == 8 + IntentCapsuleV0::LEN && data.starts_with(IntentCapsule::DISCRIMINATOR) {
+        let v0 = IntentCapsuleV0::deserialize(&mut &data[8..])?;
+        return Ok(v0.into());
+    }
+    decode(data)
+}
+
+pub fn decode_intent_data(data: &[u8]) -> Result<IntentData, Error> {
+    decode(data)
+}
+
+pub fn decode_vault(data: &[u8]) -> Result<CapsuleVault, Error> {
+    decode(data)
+}
+
+pub fn decode_fee_config(data: &[u8]) -> Result<FeeConfig, Error> {
+    decode(data)
+}
+
+pub fn decode_program_config(data: &[u8]) -> Result<ProgramConfig, Error> {
+    decode(data)
+}
+
+/// Intent of a capsule: the IntentData account for v2 capsules, the inline copy for older layouts
+pub fn capsule_intent<'a>(capsule: &'a IntentCapsule, intent_data: Option<&'a IntentData>) -> &'a [u8] {
+    match intent_data {
+        Some(intent_data) if capsule.intent_data.is_empty() => &intent_data.data,
+        _ => &capsule.intent_data,
+    }
+}
@@ -0,0 +1,569 @@
+//! One builder per program instruction. Accounts are derived from the capsule owner (and the mint
+//! for SPL capsules); optional accounts are only passed when the arguments call for them.
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::{instruction::Instruction, system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token::spl_token;
+use ephemeral_rollups_sdk::consts::{MAGIC_CONTEXT_ID, MAGIC_PROGRAM_ID};
+use heres_program::{
+    accounts, instruction, DelegateCapsuleArgs, ScheduleExecuteIntentArgs, SetKeeperBountyArgs, SetPauseFlagsArgs,
+    PERMISSION_PROGRAM_ID,
+};
+
+use crate::pda;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: heres_program::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+// Fee config (authority only unless noted)
+
+pub fn init_fee_config(
+    authority: &Pubkey,
+    fee_recipient: &Pubkey,
+    creation_fee_lamports: u64,
+    execution_fee_bps: u16,
+) -> Instruction {
+    build(
+        accounts::InitFeeConfig {
+            fee_config: pda::fee_config().0,
+            authority: *authority,
+            system_program: system_program::ID,
+        },
+        instruction::InitFeeConfig {
+            fee_recipient: *fee_recipient,
+            creation_fee_lamports,
+            execution_fee_bps,
+        },
+    )
+}
+
+fn update_fee_config_accounts(authority: &Pubkey) -> accounts::UpdateFeeConfig {
+    accounts::UpdateFeeConfig {
+        fee_config: pda::fee_config().0,
+        authority: *authority,
+    }
+}
+
+pub fn update_fee_config(authority: &Pubkey, creation_fee_lamports: u64, execution_fee_bps: u16) -> Instruction {
+    build(
+        update_fee_config_accounts(authority),
+        instruction::UpdateFeeConfig {
+            creation_fee_lamports,
+            execution_fee_bps,
+        },
+    )
+}
+
+pub fn set_fee_update_delay(authority: &Pubkey, fee_update_delay: i64) -> Instruction {
+    build(
+        update_fee_config_accounts(authority),
+        instruction::SetFeeUpdateDelay { fee_update_delay },
+    )
+}
+
+/// Applies a queued fee increase once its timelock has passed (anyone can call)
+pub fn apply_fee_update() -> Instruction {
+    build(
+        accounts::ApplyFeeUpdate {
+            fee_config: pda::fee_config().0,
+        },
+        instruction::ApplyFeeUpdate {},
+    )
+}
+
+pub fn propose_fee_authority(authority: &Pubkey, new_authority: &Pubkey) -> Instruction {
+    build(
+        update_fee_config_accounts(authority),
+        instruction::ProposeFeeAuthority {
+            new_authority: *new_authority,
+        },
+    )
+}
+
+/// Signed by the proposed authority
+pub fn accept_fee_authority(new_authority: &Pubkey) -> Instruction {
+    build(
+        accounts::AcceptFeeAuthority {
+            fee_config: pda::fee_config().0,
+            new_authority: *new_authority,
+        },
+        instruction::AcceptFeeAuthority {},
+    )
+}
+
+pub fn set_fee_recipient(authority: &Pubkey, new_fee_recipient: &Pubkey) -> Instruction {
+    build(
+        update_fee_config_accounts(authority),
+        instruction::SetFeeRecipient {
+            new_fee_recipient: *new_fee_recipient,
+        },
+    )
+}
+
+/// Upgrades a fee config written by the first deployment; the authority pays for the added fields
+pub fn migrate_fee_config(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateFeeConfig {
+            fee_config: pda::fee_config().0,
+            authority: *authority,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateFeeConfig {},
+    )
+}
+
+pub fn set_keeper_bounty(authority: &Pubkey, lamports: u64, bps: u16) -> Instruction {
+    build(
+        update_fee_config_accounts(authority),
+        instruction::SetKeeperBounty {
+            args: SetKeeperBountyArgs { lamports, bps },
+        },
+    )
+}
+
+// Program config (authority only)
+
+pub fn init_program_config(authority: &Pubkey) -> Instruction {
+    build(
+        accounts::InitProgramConfig {
+            program_config: pda::program_config().0,
+            authority: *authority,
+            system_program: system_program::ID,
+        },
+        instruction::InitProgramConfig {},
+    )
+}
+
+fn update_program_config_accounts(authority: &Pubkey) -> accounts::UpdateProgramConfig {
+    accounts::UpdateProgramConfig {
+        program_config: pda::program_config().0,
+        authority: *authority,
+    }
+}
+
+pub fn set_pause_flags(authority: &Pubkey, args: SetPauseFlagsArgs) -> Instruction {
+    build(
+        update_program_config_accounts(authority),
+        instruction::SetPauseFlags { args },
+    )
+}
+
+pub fn set_allowed_validators(authority: &Pubkey, validators: Vec<Pubkey>) -> Instruction {
+    build(
+        update_program_config_accounts(authority),
+        instruction::SetAllowedValidators { validators },
+    )
+}
+
+pub fn set_activity_oracle(authority: &Pubkey, activity_oracle: &Pubkey) -> Instruction {
+    build(
+        update_program_config_accounts(authority),
+        instruction::SetActivityOracle {
+            activity_oracle: *activity_oracle,
+        },
+    )
+}
+
+// Capsule lifecycle
+
+/// `fee_recipient` is required when the fee config charges a creation fee; pass `mint` to lock SPL
+/// tokens from the owner's ATA instead of SOL.
+pub fn create_capsule(
+    owner: &Pubkey,
+    fee_recipient: Option<&Pubkey>,
+    mint: Option<&Pubkey>,
+    inactivity_period: i64,
+    intent_data: Vec<u8>,
+) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    build(
+        accounts::CreateCapsule {
+            capsule,
+            vault,
+            intent_data: pda::intent_data(&capsule).0,
+            owner: *owner,
+            fee_config: pda::fee_config().0,
+            platform_fee_recipient: fee_recipient.copied(),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            mint: mint.copied(),
+            source_token_account: mint.map(|m| get_associated_token_address(owner, m)),
+            vault_token_account: mint.map(|m| get_associated_token_address(&vault, m)),
+            associated_token_program: associated_token::ID,
+            program_config: pda::program_config().0,
+        },
+        instruction::CreateCapsule {
+            inactivity_period,
+            intent_data,
+        },
+    )
+}
+
+pub fn update_intent(owner: &Pubkey, new_intent_data: Vec<u8>) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    build(
+        accounts::UpdateIntent {
+            capsule,
+            intent_data: pda::intent_data(&capsule).0,
+            owner: *owner,
+            system_program: system_program::ID,
+        },
+        instruction::UpdateIntent { new_intent_data },
+    )
+}
+
+pub fn set_rent_recipient(owner: &Pubkey, rent_recipient: &Pubkey) -> Instruction {
+    build(
+        accounts::SetRentRecipient {
+            capsule: pda::capsule(owner).0,
+            owner: *owner,
+        },
+        instruction::SetRentRecipient {
+            rent_recipient: *rent_recipient,
+        },
+    )
+}
+
+/// Owner check-in. Pass `on_er` when sending to the ER so an active crank task is rescheduled.
+pub fn update_activity(owner: &Pubkey, on_er: bool) -> Instruction {
+    build(
+        accounts::UpdateActivity {
+            capsule: pda::capsule(owner).0,
+            owner: *owner,
+            magic_program: on_er.then_some(MAGIC_PROGRAM_ID),
+        },
+        instruction::UpdateActivity {},
+    )
+}
+
+/// Must follow an Ed25519 instruction carrying the activity oracle's signature over
+/// `heres_program::activity_attestation_message`.
+pub fn attest_activity(owner: &Pubkey, activity_at: i64) -> Instruction {
+    build(
+        accounts::AttestActivity {
+            capsule: pda::capsule(owner).0,
+            program_config: pda::program_config().0,
+            instructions_sysvar: sysvar::instructions::ID,
+        },
+        instruction::AttestActivity { activity_at },
+    )
+}
+
+/// Must follow an Ed25519 instruction carrying the owner's signature over `heres_program::heartbeat_message`.
+pub fn update_activity_with_signature(owner: &Pubkey, nonce: u64) -> Instruction {
+    build(
+        accounts::UpdateActivityWithSignature {
+            capsule: pda::capsule(owner).0,
+            instructions_sysvar: sysvar::instructions::ID,
+        },
+        instruction::UpdateActivityWithSignature { nonce },
+    )
+}
+
+/// `authority` is the owner or any crank signer
+pub fn restart_timer(owner: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::RestartTimer {
+            capsule: pda::capsule(owner).0,
+            authority: *authority,
+        },
+        instruction::RestartTimer {},
+    )
+}
+
+/// Pass `keeper` to sign as a keeper and earn the executor bounty at distribution.
+pub fn execute_intent(owner: &Pubkey, keeper: Option<&Pubkey>) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    build(
+        accounts::ExecuteIntent {
+            capsule,
+            vault: pda::vault(owner).0,
+            permission_program: PERMISSION_PROGRAM_ID,
+            permission: pda::permission(&capsule).0,
+            program_config: pda::program_config().0,
+            keeper: keeper.copied(),
+        },
+        instruction::ExecuteIntent {},
+    )
+}
+
+/// `beneficiaries` are the intent's wallets in intent order; for SPL capsules their ATAs for `mint`
+/// are passed instead. `executor` is the keeper recorded by execute_intent, if any.
+/// Intents with more than LEGACY_MAX_BENEFICIARIES beneficiaries don't fit a legacy transaction; send this in a v0 transaction
+/// with an address lookup table holding the beneficiary accounts.
+pub fn distribute_assets(
+    owner: &Pubkey,
+    payer: &Pubkey,
+    fee_recipient: &Pubkey,
+    mint: Option<&Pubkey>,
+    beneficiaries: &[Pubkey],
+    executor: Option<&Pubkey>,
+) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    let mut ix = build(
+        accounts::DistributeAssets {
+            capsule,
+            vault,
+            intent_data: pda::intent_data(&capsule).0,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            fee_config: pda::fee_config().0,
+            platform_fee_recipient: Some(*fee_recipient),
+            mint: mint.copied(),
+            vault_token_account: mint.map(|m| get_associated_token_address(&vault, m)),
+            platform_fee_token_account: mint.map(|m| get_associated_token_address(fee_recipient, m)),
+            keeper_token_account: mint.map(|m| get_associated_token_address(payer, m)),
+            executor: executor.copied(),
+            executor_token_account: executor.zip(mint).map(|(e, m)| get_associated_token_address(e, m)),
+            payer: *payer,
+            associated_token_program: associated_token::ID,
+            program_config: pda::program_config().0,
+        },
+        instruction::DistributeAssets {},
+    );
+    ix.accounts.extend(beneficiaries.iter().map(|beneficiary| {
+        let destination = match mint {
+            Some(mint) => get_associated_token_address(beneficiary, mint),
+            None => *beneficiary,
+        };
+        AccountMeta::new(destination, false)
+    }));
+    ix
+}
+
+/// `rent_recipient` must be the capsule's designated rent recipient, or its last beneficiary when none is set.
+pub fn close_capsule(owner: &Pubkey, rent_recipient: &Pubkey, mint: Option<&Pubkey>) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    build(
+        accounts::CloseCapsule {
+            capsule,
+            vault,
+            intent_data: pda::intent_data(&capsule).0,
+            rent_recipient: *rent_recipient,
+            token_program: spl_token::ID,
+            mint: mint.copied(),
+            vault_token_account: mint.map(|m| get_associated_token_address(&vault, m)),
+            program_config: pda::program_config().0,
+        },
+        instruction::CloseCapsule {},
+    )
+}
+
+/// Pass the capsule's `mint` for SPL capsules so tokens are returned to the owner's ATA.
+pub fn cancel_capsule(owner: &Pubkey, mint: Option<&Pubkey>) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    build(
+        accounts::CancelCapsule {
+            capsule,
+            vault,
+            intent_data: pda::intent_data(&capsule).0,
+            owner: *owner,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            mint: mint.copied(),
+            vault_token_account: mint.map(|m| get_associated_token_address(&vault, m)),
+            owner_token_account: mint.map(|m| get_associated_token_address(owner, m)),
+            associated_token_program: associated_token::ID,
+        },
+        instruction::CancelCapsule {},
+    )
+}
+
+/// `previous_mint` is the mint of the cycle being replaced; its accounts are only passed when the mint changes.
+pub fn recreate_capsule(
+    owner: &Pubkey,
+    fee_recipient: Option<&Pubkey>,
+    previous_mint: Option<&Pubkey>,
+    mint: Option<&Pubkey>,
+    inactivity_period: i64,
+    intent_data: Vec<u8>,
+) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    let replaced_mint = previous_mint.filter(|previous| Some(*previous) != mint);
+    build(
+        accounts::RecreateCapsule {
+            capsule,
+            vault,
+            intent_data: pda::intent_data(&capsule).0,
+            owner: *owner,
+            fee_config: pda::fee_config().0,
+            platform_fee_recipient: fee_recipient.copied(),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            mint: mint.copied(),
+            source_token_account: mint.map(|m| get_associated_token_address(owner, m)),
+            vault_token_account: mint.map(|m| get_associated_token_address(&vault, m)),
+            previous_vault_token_account: replaced_mint.map(|m| get_associated_token_address(&vault, m)),
+            previous_owner_token_account: replaced_mint.map(|m| get_associated_token_address(owner, m)),
+            associated_token_program: associated_token::ID,
+            program_config: pda::program_config().0,
+        },
+        instruction::RecreateCapsule {
+            inactivity_period,
+            intent_data,
+        },
+    )
+}
+
+/// Upgrades an older capsule layout; `payer` funds the new IntentData account.
+pub fn migrate_capsule(payer: &Pubkey, owner: &Pubkey) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    build(
+        accounts::MigrateCapsule {
+            payer: *payer,
+            owner: *owner,
+            capsule,
+            intent_data: pda::intent_data(&capsule).0,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateCapsule {},
+    )
+}
+
+// Ephemeral Rollup delegation and crank
+
+/// Delegates capsule and vault (L1, signed by payer and owner)
+pub fn delegate_capsule(payer: &Pubkey, owner: &Pubkey, args: DelegateCapsuleArgs) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    build(
+        accounts::DelegateCapsuleInput {
+            payer: *payer,
+            owner: *owner,
+            pda: capsule,
+            vault,
+            magic_program: MAGIC_PROGRAM_ID,
+            delegation_program: pda::delegation_program_id(),
+            system_program: system_program::ID,
+            program_config: pda::program_config().0,
+            buffer_pda: pda::delegate_buffer(&capsule),
+            delegation_record_pda: pda::delegation_record(&capsule),
+            delegation_metadata_pda: pda::delegation_metadata(&capsule),
+            buffer_vault: pda::delegate_buffer(&vault),
+            delegation_record_vault: pda::delegation_record(&vault),
+            delegation_metadata_vault: pda::delegation_metadata(&vault),
+            owner_program: heres_program::ID,
+        },
+        instruction::DelegateCapsule { args },
+    )
+}
+
+/// Starts moving a delegated capsule to `new_validator` (ER, owner only)
+pub fn migrate_delegation(owner: &Pubkey, new_validator: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateDelegation {
+            owner: *owner,
+            capsule: pda::capsule(owner).0,
+            vault: pda::vault(owner).0,
+            program_config: pda::program_config().0,
+            magic_program: MAGIC_PROGRAM_ID,
+            magic_context: MAGIC_CONTEXT_ID,
+        },
+        instruction::MigrateDelegation {
+            new_validator: *new_validator,
+        },
+    )
+}
+
+/// Redelegates a capsule undelegated by migrate_delegation (L1, anyone can call)
+pub fn complete_migration(payer: &Pubkey, owner: &Pubkey) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    let vault = pda::vault(owner).0;
+    build(
+        accounts::CompleteMigration {
+            payer: *payer,
+            owner: *owner,
+            pda: capsule,
+            vault,
+            delegation_program: pda::delegation_program_id(),
+            system_program: system_program::ID,
+            program_config: pda::program_config().0,
+            buffer_pda: pda::delegate_buffer(&capsule),
+            delegation_record_pda: pda::delegation_record(&capsule),
+            delegation_metadata_pda: pda::delegation_metadata(&capsule),
+            buffer_vault: pda::delegate_buffer(&vault),
+            delegation_record_vault: pda::delegation_record(&vault),
+            delegation_metadata_vault: pda::delegation_metadata(&vault),
+            owner_program: heres_program::ID,
+        },
+        instruction::CompleteMigration {},
+    )
+}
+
+/// Schedules the execute_intent crank (ER, owner only)
+pub fn schedule_execute_intent(owner: &Pubkey, args: ScheduleExecuteIntentArgs) -> Instruction {
+    let capsule = pda::capsule(owner).0;
+    build(
+        accounts::ScheduleExecuteIntent {
+            magic_program: MAGIC_PROGRAM_ID,
+            payer: *owner,
+            capsule,
+            vault: pda::vault(owner).0,
+            permission_program: PERMISSION_PROGRAM_ID,
+            permission: pda::permission(&capsule).0,
+            program_config: pda::program_config().0,
+        },
+        instruction::ScheduleExecuteIntent { args },
+    )
+}
+
+/// Cancels the capsule's crank task (ER, owner only)
+pub fn cancel_scheduled_task(owner: &Pubkey) -> Instruction {
+    build(
+        accounts::CancelScheduledTask {
+            magic_program: MAGIC_PROGRAM_ID,
+            owner: *owner,
+            capsule: pda::capsule(owner).0,
+        },
+        instruction::CancelScheduledTask {},
+    )
+}
+
+/// Brings a delegated capsule and its vault back to L1, cancelling its crank task (ER, owner only)
+pub fn undelegate_capsule(owner: &Pubkey) -> Instruction {
+    build(
+        accounts::UndelegateCapsule {
+            owner: *owner,
+            capsule: pda::capsule(owner).0,
+            vault: pda::vault(owner).0,
+            magic_program: MAGIC_PROGRAM_ID,
+            magic_context: MAGIC_CONTEXT_ID,
+        },
+        instruction::UndelegateCapsule {},
+    )
+}
+
+/// Commits an executed capsule and its vault to L1 and undelegates them (ER, anyone can call)
+pub fn commit_and_undelegate_capsule(owner: &Pubkey) -> Instruction {
+    build(
+        accounts::CommitAndUndelegateCapsule {
+            capsule: pda::capsule(owner).0,
+            vault: pda::vault(owner).0,
+            magic_program: MAGIC_PROGRAM_ID,
+            magic_context: MAGIC_CONTEXT_ID,
+        },
+        instruction::CommitAndUndelegateCapsule {},
+    )
+}
+
+pub fn sample_price(payer: &Pubkey, price_update: &Pubkey) -> Instruction {
+    build(
+        accounts::SamplePrice {
+            payer: *payer,
+            price_update: *price_update,
+        },
+        instruction::SamplePrice {},
+    )
+}
@@ -0,0 +1,110 @@
+//! Intent JSON, in the shape the web app writes (`utils/intent.ts`) and the program parses.
+
+use anchor_lang::prelude::Pubkey;
+use serde::{Deserialize, Serialize};
+
+use crate::LAMPORTS_PER_SOL;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountType {
+    /// `amount` is in SOL (or whole tokens at 9 decimals)
+    Fixed,
+    /// `amount` is a percent of totalAmount
+    Percentage,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Beneficiary {
+    #[serde(with = "pubkey_string")]
+    pub address: Pubkey,
+    pub amount: String,
+    pub amount_type: AmountType,
+}
+
+impl Beneficiary {
+    /// Beneficiary receiving a fixed `lamports` (or token base units)
+    pub fn fixed(address: Pubkey, lamports: u64) -> Self {
+        Beneficiary {
+            address,
+            amount: format_amount(lamports),
+            amount_type: AmountType::Fixed,
+        }
+    }
+
+    /// Beneficiary receiving `percent` of totalAmount
+    pub fn percentage(address: Pubkey, percent: f64) -> Self {
+        Beneficiary {
+            address,
+            amount: percent.to_string(),
+            amount_type: AmountType::Percentage,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Intent {
+    /// Free-form message from the owner
+    pub intent: String,
+    pub beneficiaries: Vec<Beneficiary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_amount: Option<String>,
+    #[serde(default)]
+    pub inactivity_days: u64,
+    #[serde(default)]
+    pub delay_days: u64,
+}
+
+impl Intent {
+    /// Intent locking `total_lamports` (or token base units) for `beneficiaries`
+    pub fn new(intent: impl Into<String>, total_lamports: u64, beneficiaries: Vec<Beneficiary>) -> Self {
+        Intent {
+            intent: intent.into(),
+            beneficiaries,
+            total_amount: Some(format_amount(total_lamports)),
+            inactivity_days: 0,
+            delay_days: 0,
+        }
+    }
+
+    /// JSON bytes as passed to create_capsule, update_intent and recreate_capsule
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("intent serializes to JSON")
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(data)
+    }
+
+    /// Run the program's write-time checks locally and return totalAmount in base units
+    pub fn validate(&self) -> anchor_lang::Result<u64> {
+        heres_program::validate_intent(&self.encode())
+    }
+}
+
+/// Format base units (9 decimals) the way intents write amounts, e.g. 1_500_000_000 -> "1.5"
+pub fn format_amount(base_units: u64) -> String {
+    let whole = base_units / LAMPORTS_PER_SOL;
+    let fraction = base_units % LAMPORTS_PER_SOL;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:09}", fraction);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+mod pubkey_string {
+    use anchor_lang::prelude::Pubkey;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        address.parse().map_err(|_| D::Error::custom(format!("invalid pubkey {:?}", address)))
+    }
+}
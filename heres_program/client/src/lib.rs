@@ -0,0 +1,24 @@
+//! Rust client for the Heres program.
+//!
+//! Mirrors what `lib/program.ts` and `utils/intent.ts` do for the web app: derive the program's PDAs,
+//! build every instruction with the right accounts, decode program accounts and encode intents.
+//! Builders return plain `Instruction`s; signing and sending is left to the caller.
+
+pub mod accounts;
+pub mod instructions;
+pub mod intent;
+pub mod pda;
+
+pub use heres_program::{
+    ActivitySource, CapsuleVault, DelegateCapsuleArgs, FeeConfig, FeeKind, IntentCapsule, IntentData,
+    ProgramConfig, ScheduleExecuteIntentArgs, SetKeeperBountyArgs, SetPauseFlagsArgs, ID as PROGRAM_ID,
+    MAX_BENEFICIARIES, MAX_INTENT_DATA_LEN, PERMISSION_PROGRAM_ID, TEE_VALIDATOR,
+};
+pub use intent::{AmountType, Beneficiary, Intent};
+
+/// Lamports in one SOL; intent amounts are written in SOL (or whole tokens at 9 decimals)
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+/// Most beneficiaries a legacy distribute_assets transaction can pay (worst case: SPL capsule with an
+/// executor bounty); past this a distribution needs a v0 transaction with an address lookup table
+pub const LEGACY_MAX_BENEFICIARIES: usize = 15;
@@ -0,0 +1,55 @@
+//! PDA derivation, matching the seeds the program checks.
+
+use anchor_lang::prelude::Pubkey;
+use ephemeral_rollups_sdk::pda::{DELEGATE_BUFFER_TAG, DELEGATION_METADATA_TAG, DELEGATION_RECORD_TAG};
+use heres_program::PERMISSION_PROGRAM_ID;
+
+/// Delegation program that owns delegated capsules and vaults while they live on the ER
+pub fn delegation_program_id() -> Pubkey {
+    Pubkey::new_from_array(ephemeral_rollups_sdk::id().to_bytes())
+}
+
+/// Fee config PDA: ["fee_config"]
+pub fn fee_config() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"fee_config"], &heres_program::ID)
+}
+
+/// Program config PDA: ["program_config"]
+pub fn program_config() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"program_config"], &heres_program::ID)
+}
+
+/// Capsule PDA: ["intent_capsule", owner]
+pub fn capsule(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"intent_capsule", owner.as_ref()], &heres_program::ID)
+}
+
+/// Vault PDA: ["capsule_vault", owner]
+pub fn vault(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"capsule_vault", owner.as_ref()], &heres_program::ID)
+}
+
+/// IntentData PDA: ["intent_data", capsule]
+pub fn intent_data(capsule: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"intent_data", capsule.as_ref()], &heres_program::ID)
+}
+
+/// Access-control PDA of the MagicBlock permission program: ["permission", capsule]
+pub fn permission(capsule: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"permission", capsule.as_ref()], &PERMISSION_PROGRAM_ID)
+}
+
+/// Delegation buffer of `delegated` (owned by this program while delegating)
+pub fn delegate_buffer(delegated: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[DELEGATE_BUFFER_TAG, delegated.as_ref()], &heres_program::ID).0
+}
+
+/// Delegation record of `delegated` (owned by the delegation program)
+pub fn delegation_record(delegated: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[DELEGATION_RECORD_TAG, delegated.as_ref()], &delegation_program_id()).0
+}
+
+/// Delegation metadata of `delegated` (owned by the delegation program)
+pub fn delegation_metadata(delegated: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[DELEGATION_METADATA_TAG, delegated.as_ref()], &delegation_program_id()).0
+}
@@ -0,0 +1,118 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountSerialize, AnchorSerialize, Discriminator};
+use heres_client::intent::format_amount;
+use heres_client::{accounts, instructions, pda, Beneficiary, Intent, IntentCapsule, LAMPORTS_PER_SOL};
+use heres_program::IntentCapsuleV0;
+
+#[test]
+fn builders_match_the_program_crank_instructions() {
+    let owner = Pubkey::new_unique();
+    let capsule = pda::capsule(&owner).0;
+    let vault_bump = pda::vault(&owner).1;
+
+    assert_eq!(
+        instructions::execute_intent(&owner, None),
+        heres_program::execute_intent_crank_ix(&capsule, &owner, vault_bump).unwrap()
+    );
+    assert_eq!(
+        instructions::commit_and_undelegate_capsule(&owner),
+        heres_program::commit_and_undelegate_crank_ix(&capsule, &owner, vault_bump).unwrap()
+    );
+}
+
+#[test]
+fn distribute_passes_beneficiaries_or_their_atas_last() {
+    let owner = Pubkey::new_unique();
+    let payer = Pubkey::new_unique();
+    let fee_recipient = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let heirs = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+    let sol = instructions::distribute_assets(&owner, &payer, &fee_recipient, None, &heirs, None);
+    let tail: Vec<_> = sol.accounts[sol.accounts.len() - 2..].iter().map(|m| m.pubkey).collect();
+    assert_eq!(tail, heirs);
+
+    let spl = instructions::distribute_assets(&owner, &payer, &fee_recipient, Some(&mint), &heirs, None);
+    let tail: Vec<_> = spl.accounts[spl.accounts.len() - 2..].iter().map(|m| m.pubkey).collect();
+    let atas: Vec<_> = heirs
+        .iter()
+        .map(|h| anchor_spl::associated_token::get_associated_token_address(h, &mint))
+        .collect();
+    assert_eq!(tail, atas);
+    assert_eq!(spl.accounts.len(), sol.accounts.len());
+}
+
+#[test]
+fn intent_encodes_in_the_web_app_shape_and_passes_validation() {
+    let heir_a = Pubkey::new_unique();
+    let heir_b = Pubkey::new_unique();
+    let intent = Intent::new(
+        "for the kids",
+        3 * LAMPORTS_PER_SOL / 2,
+        vec![
+            Beneficiary::fixed(heir_a, LAMPORTS_PER_SOL / 2),
+            Beneficiary::fixed(heir_b, LAMPORTS_PER_SOL),
+        ],
+    );
+
+    let json: serde_json::Value = serde_json::from_slice(&intent.encode()).unwrap();
+    assert_eq!(json["totalAmount"], "1.5");
+    assert_eq!(json["beneficiaries"][0]["address"], heir_a.to_string());
+    assert_eq!(json["beneficiaries"][0]["amount"], "0.5");
+    assert_eq!(json["beneficiaries"][0]["amountType"], "fixed");
+    assert_eq!(intent.validate().unwrap(), 3 * LAMPORTS_PER_SOL / 2);
+    assert_eq!(Intent::decode(&intent.encode()).unwrap(), intent);
+
+    let split = Intent::new(
+        "",
+        LAMPORTS_PER_SOL,
+        vec![Beneficiary::percentage(heir_a, 60.0), Beneficiary::percentage(heir_b, 40.0)],
+    );
+    assert_eq!(split.validate().unwrap(), LAMPORTS_PER_SOL);
+    assert!(Intent::new("", LAMPORTS_PER_SOL, vec![Beneficiary::percentage(heir_a, 60.0)])
+        .validate()
+        .is_err());
+}
+
+#[test]
+fn format_amount_trims_trailing_zeros() {
+    assert_eq!(format_amount(0), "0");
+    assert_eq!(format_amount(LAMPORTS_PER_SOL), "1");
+    assert_eq!(format_amount(1), "0.000000001");
+    assert_eq!(format_amount(2_050_000_000), "2.05");
+}
+
+#[test]
+fn decode_capsule_reads_current_and_v0_layouts() {
+    let owner = Pubkey::new_unique();
+    let intent = Intent::new("", LAMPORTS_PER_SOL, vec![Beneficiary::percentage(owner, 100.0)]).encode();
+    let v0 = IntentCapsuleV0 {
+        owner,
+        inactivity_period: 60,
+        last_activity: 1_700_000_000,
+        intent_data: intent.clone(),
+        is_active: true,
+        executed_at: None,
+        bump: 254,
+        vault_bump: 253,
+        mint: Pubkey::default(),
+        retry_count: 0,
+    };
+
+    let mut v0_bytes = IntentCapsule::DISCRIMINATOR.to_vec();
+    v0.serialize(&mut v0_bytes).unwrap();
+    v0_bytes.resize(8 + IntentCapsuleV0::LEN, 0);
+    let decoded = accounts::decode_capsule(&v0_bytes).unwrap();
+    assert_eq!(decoded.version, 0);
+    assert_eq!(decoded.owner, owner);
+    assert_eq!(accounts::capsule_intent(&decoded, None), intent.as_slice());
+
+    let mut current = IntentCapsule::from(v0);
+    current.intent_data.clear();
+    current.version = IntentCapsule::VERSION;
+    let mut current_bytes = Vec::new();
+    current.try_serialize(&mut current_bytes).unwrap();
+    let decoded = accounts::decode_capsule(&current_bytes).unwrap();
+    assert_eq!(decoded.version, IntentCapsule::VERSION);
+    assert_eq!(decoded.inactivity_period, 60);
+}
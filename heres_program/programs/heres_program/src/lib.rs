@@ -1,21 +1,21 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
+use anchor_lang::{InstructionData, ToAccountMetas};
 use anchor_lang::solana_program::{
     instruction::{AccountMeta, Instruction},
     program::invoke_signed,
     pubkey::pubkey,
 };
 use ephemeral_rollups_sdk::anchor::{commit, delegate, ephemeral};
+use ephemeral_rollups_sdk::ephem::{commit_and_undelegate_accounts, create_schedule_commit_ix};
 use ephemeral_rollups_sdk::cpi::DelegateConfig;
-use ephemeral_rollups_sdk::ephem::commit_and_undelegate_accounts;
-use ephemeral_rollups_sdk::consts::MAGIC_PROGRAM_ID;
-use ephemeral_rollups_sdk::access_control::{
-    instructions::CreatePermissionCpiBuilder,
-    structs::{Member, MembersArgs, AUTHORITY_FLAG, TX_LOGS_FLAG, TX_BALANCES_FLAG, TX_MESSAGE_FLAG, ACCOUNT_SIGNATURES_FLAG}
-};
+use ephemeral_rollups_sdk::consts::{MAGIC_CONTEXT_ID, MAGIC_PROGRAM_ID};
 use magicblock_magic_program_api::{args::ScheduleTaskArgs, instruction::MagicBlockInstruction};
-use anchor_spl::token::{self, Token, TokenAccount, Transfer, Mint};
-use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, CloseAccount, Token, TokenAccount, Transfer, Mint};
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+use solana_sdk_ids::{ed25519_program, sysvar::instructions as instructions_sysvar};
+use solana_sha256_hasher::hashv;
 #[cfg(feature = "oracle")]
 use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
@@ -24,12 +24,23 @@ declare_id!("CXVKwAjzQA95MPVyEbsMqSoFgHvbXAmSensTk6JJPKsM");
 /// TEE validator for Private Ephemeral Rollup (PER). Used as default when no validator account is passed.
 pub const TEE_VALIDATOR: Pubkey = pubkey!("FnE6VJT5QNZdedZPnCoLsARgBwoE6DeJNjBs2H1gySXA");
 
+/// Shortest periodic commit interval accepted by delegate_capsule, to bound L1 commit costs
+pub const MIN_COMMIT_FREQUENCY_MS: u32 = 10_000;
+
+/// Maximum number of entries in ProgramConfig.allowed_validators
+pub const MAX_ALLOWED_VALIDATORS: usize = 8;
+
+/// Maximum number of beneficiaries in an intent; distribute_assets pays each one in a single transaction.
+/// A legacy transaction only has room for 15 of them, so larger intents need a v0 transaction that loads
+/// the beneficiary accounts from an address lookup table
+pub const MAX_BENEFICIARIES: usize = 25;
+
+/// Largest intent the IntentData account can hold (the most one realloc can grow an account by)
+pub const MAX_INTENT_DATA_LEN: usize = 10_240;
+
 /// MagicBlock Permission Program ID for Access Control
 pub const PERMISSION_PROGRAM_ID: Pubkey = pubkey!("ACLseoPoyC3cBqoUtkbjZ4aDrkurZW86v19pXz2XQnp1");
 
-/// Discriminator for execute_intent (no args) ??from IDL
-const EXECUTE_INTENT_DISCRIMINATOR: [u8; 8] = [53, 130, 47, 154, 227, 220, 122, 212];
-
 #[ephemeral]
 #[program]
 pub mod heres_program {
@@ -48,11 +59,23 @@ pub mod heres_program {
         config.fee_recipient = fee_recipient;
         config.creation_fee_lamports = creation_fee_lamports;
         config.execution_fee_bps = execution_fee_bps;
+        config.pending_authority = None;
+        config.fee_update_delay = 0;
+        config.pending_fees_effective_at = None;
+        config.pending_delay_effective_at = None;
         msg!("Fee config initialized: recipient={:?}, creation_fee={}, execution_bps={}", fee_recipient, creation_fee_lamports, execution_fee_bps);
+        emit!(FeeConfigInitialized {
+            authority: config.authority,
+            fee_recipient,
+            creation_fee_lamports,
+            execution_fee_bps,
+        });
         Ok(())
     }
 
-    /// Update platform fee config (authority only)
+    /// Update platform fee config (authority only).
+    /// Fee decreases apply immediately. Increases are queued behind `fee_update_delay` when a
+    /// delay is set and take effect via apply_fee_update; a non-increasing update cancels any queued increase.
     pub fn update_fee_config(
         ctx: Context<UpdateFeeConfig>,
         creation_fee_lamports: u64,
@@ -61,9 +84,252 @@ pub mod heres_program {
         require!(execution_fee_bps <= 10000, ErrorCode::InvalidFeeConfig);
         let config = &mut ctx.accounts.fee_config;
         require!(config.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        let is_increase = creation_fee_lamports > config.creation_fee_lamports
+            || execution_fee_bps > config.execution_fee_bps;
+        if is_increase && config.fee_update_delay > 0 {
+            let effective_at = Clock::get()?
+                .unix_timestamp
+                .checked_add(config.fee_update_delay)
+                .ok_or(ErrorCode::InvalidFeeConfig)?;
+            config.pending_creation_fee_lamports = creation_fee_lamports;
+            config.pending_execution_fee_bps = execution_fee_bps;
+            config.pending_fees_effective_at = Some(effective_at);
+            msg!("Fee increase queued: creation_fee={}, execution_bps={}, effective_at={}", creation_fee_lamports, execution_fee_bps, effective_at);
+            emit!(FeeUpdateQueued {
+                creation_fee_lamports,
+                execution_fee_bps,
+                effective_at,
+            });
+            return Ok(());
+        }
+
         config.creation_fee_lamports = creation_fee_lamports;
         config.execution_fee_bps = execution_fee_bps;
+        config.pending_fees_effective_at = None;
         msg!("Fee config updated: creation_fee={}, execution_bps={}", creation_fee_lamports, execution_fee_bps);
+        emit!(FeeConfigUpdated {
+            creation_fee_lamports,
+            execution_fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Set the timelock (seconds) applied to fee increases (authority only).
+    /// Lengthening applies immediately; shortening is itself delayed by the current timelock.
+    pub fn set_fee_update_delay(ctx: Context<UpdateFeeConfig>, fee_update_delay: i64) -> Result<()> {
+        require!(fee_update_delay >= 0, ErrorCode::InvalidFeeConfig);
+        let config = &mut ctx.accounts.fee_config;
+
+        if fee_update_delay < config.fee_update_delay {
+            let effective_at = Clock::get()?
+                .unix_timestamp
+                .checked_add(config.fee_update_delay)
+                .ok_or(ErrorCode::InvalidFeeConfig)?;
+            config.pending_fee_update_delay = fee_update_delay;
+            config.pending_delay_effective_at = Some(effective_at);
+            msg!("Fee update delay reduction queued: delay={}, effective_at={}", fee_update_delay, effective_at);
+            emit!(FeeUpdateDelayQueued {
+                fee_update_delay,
+                effective_at,
+            });
+            return Ok(());
+        }
+
+        config.fee_update_delay = fee_update_delay;
+        config.pending_delay_effective_at = None;
+        msg!("Fee update delay set: {}", fee_update_delay);
+        emit!(FeeUpdateDelayUpdated { fee_update_delay });
+        Ok(())
+    }
+
+    /// Apply queued fee / delay changes whose timelock has elapsed. Anyone can call.
+    pub fn apply_fee_update(ctx: Context<ApplyFeeUpdate>) -> Result<()> {
+        let config = &mut ctx.accounts.fee_config;
+        let now = Clock::get()?.unix_timestamp;
+        let fees_ready = config.pending_fees_effective_at.is_some_and(|t| now >= t);
+        let delay_ready = config.pending_delay_effective_at.is_some_and(|t| now >= t);
+        require!(
+            config.pending_fees_effective_at.is_some() || config.pending_delay_effective_at.is_some(),
+            ErrorCode::NoPendingFeeUpdate
+        );
+        require!(fees_ready || delay_ready, ErrorCode::FeeUpdateNotReady);
+
+        if fees_ready {
+            config.creation_fee_lamports = config.pending_creation_fee_lamports;
+            config.execution_fee_bps = config.pending_execution_fee_bps;
+            config.pending_fees_effective_at = None;
+            msg!("Queued fee update applied: creation_fee={}, execution_bps={}", config.creation_fee_lamports, config.execution_fee_bps);
+            emit!(FeeConfigUpdated {
+                creation_fee_lamports: config.creation_fee_lamports,
+                execution_fee_bps: config.execution_fee_bps,
+            });
+        }
+        if delay_ready {
+            config.fee_update_delay = config.pending_fee_update_delay;
+            config.pending_delay_effective_at = None;
+            msg!("Queued fee update delay applied: {}", config.fee_update_delay);
+            emit!(FeeUpdateDelayUpdated {
+                fee_update_delay: config.fee_update_delay,
+            });
+        }
+        Ok(())
+    }
+
+    /// Propose a new fee config authority (authority only). Takes effect once the proposed key calls accept_fee_authority.
+    pub fn propose_fee_authority(ctx: Context<UpdateFeeConfig>, new_authority: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.fee_config;
+        config.pending_authority = Some(new_authority);
+        msg!("Fee authority proposed: {:?}", new_authority);
+        emit!(FeeAuthorityProposed {
+            authority: config.authority,
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    /// Accept a pending fee config authority transfer (must be signed by the proposed authority)
+    pub fn accept_fee_authority(ctx: Context<AcceptFeeAuthority>) -> Result<()> {
+        let config = &mut ctx.accounts.fee_config;
+        let previous_authority = config.authority;
+        config.authority = ctx.accounts.new_authority.key();
+        config.pending_authority = None;
+        msg!("Fee authority transferred: {:?} -> {:?}", previous_authority, config.authority);
+        emit!(FeeAuthorityTransferred {
+            previous_authority,
+            new_authority: config.authority,
+        });
+        Ok(())
+    }
+
+    /// Rotate the platform fee recipient wallet (authority only)
+    pub fn set_fee_recipient(ctx: Context<UpdateFeeConfig>, new_fee_recipient: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.fee_config;
+        let previous_fee_recipient = config.fee_recipient;
+        config.fee_recipient = new_fee_recipient;
+        msg!("Fee recipient updated: {:?} -> {:?}", previous_fee_recipient, new_fee_recipient);
+        emit!(FeeRecipientUpdated {
+            previous_fee_recipient,
+            new_fee_recipient,
+        });
+        Ok(())
+    }
+
+    /// Set the bounty paid to the first keeper that executes and to the first that distributes a capsule
+    /// (authority only): a fixed amount when `lamports` is non-zero, otherwise `bps` of the vault balance.
+    /// Bounties are carved out of the execution fee, so they apply immediately without the fee timelock.
+    pub fn set_keeper_bounty(ctx: Context<UpdateFeeConfig>, args: SetKeeperBountyArgs) -> Result<()> {
+        require!(args.bps <= 10000, ErrorCode::InvalidFeeConfig);
+        let config = &mut ctx.accounts.fee_config;
+        config.keeper_bounty_lamports = args.lamports;
+        config.keeper_bounty_bps = args.bps;
+        msg!("Keeper bounty set: {} lamports / {} bps", args.lamports, args.bps);
+        emit!(KeeperBountyUpdated {
+            keeper_bounty_lamports: args.lamports,
+            keeper_bounty_bps: args.bps,
+        });
+        Ok(())
+    }
+
+    /// Upgrade a fee config written by the first deployment to the current layout (authority only). The new
+    /// fields start at their init defaults: no pending authority or fee change, no timelock, no keeper bounty.
+    pub fn migrate_fee_config(ctx: Context<MigrateFeeConfig>) -> Result<()> {
+        let fee_config_info = ctx.accounts.fee_config.to_account_info();
+        let v0 = {
+            let data = fee_config_info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *FeeConfig::DISCRIMINATOR,
+                ErrorCode::InvalidFeeConfig
+            );
+            require!(data.len() != 8 + FeeConfig::LEN, ErrorCode::FeeConfigAlreadyMigrated);
+            require!(data.len() == 8 + FeeConfigV0::LEN, ErrorCode::InvalidFeeConfig);
+            FeeConfigV0::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::InvalidFeeConfig)?
+        };
+        require!(v0.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        // The authority covers the rent for the added fields
+        let new_len = 8 + FeeConfig::LEN;
+        let rent_due = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(fee_config_info.lamports());
+        if rent_due > 0 {
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.authority.to_account_info(),
+                to: fee_config_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+            system_program::transfer(cpi_ctx, rent_due)?;
+        }
+        fee_config_info.resize(new_len)?;
+
+        let config = FeeConfig::from(v0);
+        config.try_serialize(&mut &mut fee_config_info.try_borrow_mut_data()?[..])?;
+        msg!("Fee config migrated to the current layout");
+        emit!(FeeConfigMigrated {
+            authority: config.authority,
+            fee_recipient: config.fee_recipient,
+            creation_fee_lamports: config.creation_fee_lamports,
+            execution_fee_bps: config.execution_fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Initialize program config (admin authority + pause flags). Call once after deploy.
+    pub fn init_program_config(ctx: Context<InitProgramConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.authority = ctx.accounts.authority.key();
+        config.pause_create = false;
+        config.pause_execute = false;
+        config.pause_distribute = false;
+        msg!("Program config initialized: authority={:?}", config.authority);
+        emit!(ProgramConfigInitialized {
+            authority: config.authority,
+        });
+        Ok(())
+    }
+
+    /// Emergency circuit breaker (admin authority only). Pauses create / execute / distribute independently.
+    /// cancel_capsule, undelegate_capsule and update_activity are never paused so owners can always withdraw
+    /// or check in.
+    pub fn set_pause_flags(ctx: Context<UpdateProgramConfig>, args: SetPauseFlagsArgs) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.pause_create = args.create;
+        config.pause_execute = args.execute;
+        config.pause_distribute = args.distribute;
+        msg!("Pause flags set: create={}, execute={}, distribute={}", args.create, args.execute, args.distribute);
+        emit!(PauseFlagsUpdated {
+            authority: config.authority,
+            pause_create: args.create,
+            pause_execute: args.execute,
+            pause_distribute: args.distribute,
+        });
+        Ok(())
+    }
+
+    /// Replace the ER validators capsules may be delegated to (admin only).
+    /// An empty list allows only TEE_VALIDATOR.
+    pub fn set_allowed_validators(ctx: Context<UpdateProgramConfig>, validators: Vec<Pubkey>) -> Result<()> {
+        require!(validators.len() <= MAX_ALLOWED_VALIDATORS, ErrorCode::TooManyValidators);
+        let config = &mut ctx.accounts.program_config;
+        config.allowed_validators = validators.clone();
+        msg!("Allowed validators set: {:?}", validators);
+        emit!(AllowedValidatorsUpdated {
+            authority: config.authority,
+            validators,
+        });
+        Ok(())
+    }
+
+    /// Register the activity oracle whose attestations attest_activity accepts (admin only).
+    /// Pubkey::default() disables attestations.
+    pub fn set_activity_oracle(ctx: Context<UpdateProgramConfig>, activity_oracle: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.activity_oracle = activity_oracle;
+        msg!("Activity oracle set to {:?}", activity_oracle);
+        emit!(ActivityOracleUpdated {
+            authority: config.authority,
+            activity_oracle,
+        });
         Ok(())
     }
 
@@ -74,17 +340,8 @@ pub mod heres_program {
         inactivity_period: i64,
         intent_data: Vec<u8>,
     ) -> Result<()> {
-        // Parse totalAmount from intent_data
-        let total_amount_lamports = {
-            let intent_data_str = String::from_utf8(intent_data.clone())
-                .map_err(|_| ErrorCode::InvalidIntentData)?;
-            let intent_json: serde_json::Value = serde_json::from_str(&intent_data_str)
-                .map_err(|_| ErrorCode::InvalidIntentData)?;
-            let total_str = intent_json.get("totalAmount")
-                .and_then(|t| t.as_str())
-                .ok_or(ErrorCode::InvalidIntentData)?;
-            parse_sol_to_lamports(total_str).map_err(|_| ErrorCode::InvalidIntentData)?
-        };
+        // The full intent is checked here so a bad one never has to be discovered at distribution
+        let total_amount_lamports = validate_intent(&intent_data)?;
 
         let fee_config = &ctx.accounts.fee_config;
         if fee_config.creation_fee_lamports > 0 {
@@ -100,16 +357,32 @@ pub mod heres_program {
             let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
             system_program::transfer(cpi_ctx, fee_config.creation_fee_lamports)?;
             msg!("Creation fee {} lamports sent to platform recipient: {:?}", fee_config.creation_fee_lamports, platform_recipient.key());
+            emit!(FeeCharged {
+                capsule: ctx.accounts.capsule.key(),
+                owner: ctx.accounts.owner.key(),
+                kind: FeeKind::Creation,
+                recipient: platform_recipient.key(),
+                mint: Pubkey::default(),
+                amount: fee_config.creation_fee_lamports,
+            });
         }
 
+        let intent = &mut ctx.accounts.intent_data;
+        intent.capsule = ctx.accounts.capsule.key();
+        intent.bump = ctx.bumps.intent_data;
+        intent.data = intent_data;
+
         let capsule = &mut ctx.accounts.capsule;
         capsule.owner = ctx.accounts.owner.key();
         capsule.inactivity_period = inactivity_period;
         capsule.last_activity = Clock::get()?.unix_timestamp;
-        capsule.intent_data = intent_data;
         capsule.is_active = true;
         capsule.bump = ctx.bumps.capsule;
         capsule.vault_bump = ctx.bumps.vault;
+        capsule.rent_recipient = Pubkey::default();
+        capsule.distributed_at = None;
+        capsule.version = IntentCapsule::VERSION;
+        capsule.locked_amount = total_amount_lamports;
 
         // Check if SPL Mint is provided
         if let Some(mint) = &ctx.accounts.mint {
@@ -143,6 +416,15 @@ pub mod heres_program {
 
 
         msg!("Intent Capsule created: {:?}", ctx.accounts.capsule.key());
+        let capsule = &ctx.accounts.capsule;
+        emit!(CapsuleCreated {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+            mint: capsule.mint,
+            total_amount: total_amount_lamports,
+            inactivity_period: capsule.inactivity_period,
+            created_at: capsule.last_activity,
+        });
         Ok(())
     }
 
@@ -154,11 +436,22 @@ pub mod heres_program {
         let capsule = &mut ctx.accounts.capsule;
         require!(capsule.owner == ctx.accounts.owner.key(), ErrorCode::Unauthorized);
         require!(capsule.is_active, ErrorCode::CapsuleInactive);
+
+        // Nothing is deposited on update, so the intent cannot promise more than was locked
+        let total_amount = validate_intent(&new_intent_data)?;
+        require!(total_amount <= capsule.locked_amount, ErrorCode::TotalExceedsLockedAmount);
+        ctx.accounts.intent_data.data = new_intent_data;
         
-        capsule.intent_data = new_intent_data;
         capsule.last_activity = Clock::get()?.unix_timestamp;
         
         msg!("Intent updated for capsule: {:?}", capsule.key());
+        emit!(IntentUpdated {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+            total_amount,
+            intent_len: ctx.accounts.intent_data.data.len() as u32,
+            updated_at: capsule.last_activity,
+        });
         Ok(())
     }
 
@@ -186,6 +479,10 @@ pub mod heres_program {
         
         capsule.is_active = false;
         capsule.executed_at = Some(current_time);
+        // The crank has done its job; any iterations it has left fail on CapsuleInactive
+        capsule.crank_task_id = None;
+        // Only the first successful execution gets here, so its signer is the one owed the bounty
+        capsule.executed_by = ctx.accounts.keeper.as_ref().map(|k| k.key()).unwrap_or_default();
         
         msg!("Intent executed (state updated) for capsule: {:?}", capsule.key());
         emit!(IntentExecuted {
@@ -210,33 +507,28 @@ pub mod heres_program {
         capsule.retry_count += 1;
         
         msg!("Timer restarted for capsule: {:?}. New last_activity: {}", capsule.key(), capsule.last_activity);
+        emit!(TimerRestarted {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+            retry_count: capsule.retry_count,
+            last_activity: capsule.last_activity,
+        });
         Ok(())
     }
 
     /// Distribute assets from the vault to beneficiaries. Call on base layer after execute_intent.
+    /// Permissionless: the signing keeper (payer) and the keeper that executed the capsule receive the keeper bounty.
     pub fn distribute_assets<'info>(
         ctx: Context<'_, '_, '_, 'info, DistributeAssets<'info>>,
     ) -> Result<()> {
         let capsule = &ctx.accounts.capsule;
         require!(!capsule.is_active, ErrorCode::CapsuleActive);
         require!(capsule.executed_at.is_some(), ErrorCode::CapsuleNotExecuted);
+        require!(capsule.distributed_at.is_none(), ErrorCode::AlreadyDistributed);
         
-        // Parse intent data
-        let intent_data_str = String::from_utf8(capsule.intent_data.clone())
-            .map_err(|_| ErrorCode::InvalidIntentData)?;
-        let intent_json: serde_json::Value = serde_json::from_str(&intent_data_str)
-            .map_err(|_| ErrorCode::InvalidIntentData)?;
-        
-        let beneficiaries = intent_json.get("beneficiaries")
-            .and_then(|b| b.as_array())
-            .ok_or(ErrorCode::InvalidIntentData)?;
-        
-        let total_amount_str = intent_json.get("totalAmount")
-            .and_then(|t| t.as_str())
-            .ok_or(ErrorCode::InvalidIntentData)?;
-        
-        let total_amount_lamports = parse_sol_to_lamports(total_amount_str)
-            .map_err(|_| ErrorCode::InvalidIntentData)?;
+        // Parse intent data (structure only: intents written before validation existed must still pay out)
+        let intent = parse_intent(&ctx.accounts.intent_data.data)?;
+        let total_amount_lamports = intent.total_amount;
         
         let vault_bump = capsule.vault_bump;
         let owner_key = capsule.owner;
@@ -247,87 +539,156 @@ pub mod heres_program {
         ];
         let signer_seeds = &[vault_seeds];
         
-        // Platform execution fee
-        let fee_config = &ctx.accounts.fee_config;
-        let mut remaining_for_beneficiaries = total_amount_lamports;
         let is_spl = capsule.mint != Pubkey::default();
+        if is_spl {
+            let mint = ctx.accounts.mint.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+            require!(mint.key() == capsule.mint, ErrorCode::InvalidTokenAccount);
+        }
+
+        // Pay out what the vault actually holds, not the JSON totalAmount (which may be stale or oversized).
+        // SOL vaults keep their rent-exempt minimum; it is returned to the rent recipient when the vault is closed.
+        let distributable = if is_spl {
+            ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?.amount
+        } else {
+            let vault_info = ctx.accounts.vault.to_account_info();
+            let rent_minimum = Rent::get()?.minimum_balance(vault_info.data_len());
+            vault_info.lamports()
+                .checked_sub(rent_minimum)
+                .ok_or(ErrorCode::InsufficientVaultBalance)?
+        };
+        if distributable != total_amount_lamports {
+            msg!("Vault holds {} but intent totalAmount is {}; distributing vault balance", distributable, total_amount_lamports);
+        }
+
+        // Platform execution fee; beneficiaries share the rest (see compute_distribution)
+        let fee_config = &ctx.accounts.fee_config;
+        let shares = compute_distribution(distributable, fee_config.execution_fee_bps, &intent);
 
         if fee_config.execution_fee_bps > 0 {
-            let execution_fee = (total_amount_lamports as u64)
-                .checked_mul(fee_config.execution_fee_bps as u64)
-                .and_then(|v| v.checked_div(10_000))
-                .ok_or(ErrorCode::InvalidIntentData)?;
+            let execution_fee = execution_fee(distributable, fee_config.execution_fee_bps);
             
             if execution_fee > 0 {
-                let platform_recipient = ctx.accounts.platform_fee_recipient.as_mut().ok_or(ErrorCode::InvalidFeeConfig)?;
+                let platform_recipient = ctx.accounts.platform_fee_recipient.as_ref().ok_or(ErrorCode::InvalidFeeConfig)?;
                 require!(platform_recipient.key() == fee_config.fee_recipient, ErrorCode::InvalidFeeConfig);
-                
-                if is_spl {
-                     let vault_ata = ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
-                     let cpi_accounts = Transfer {
-                        from: vault_ata.to_account_info(),
-                        to: platform_recipient.to_account_info(),
-                        authority: ctx.accounts.vault.to_account_info(),
-                     };
-                     let cpi_program = ctx.accounts.token_program.to_account_info();
-                     let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
-                     token::transfer(cpi_ctx, execution_fee)?;
+
+                // Keeper bounties are carved out of the execution fee, so beneficiaries receive the same amount.
+                // Execution and distribution each succeed only once, so only the first successful caller is paid.
+                let bounty = fee_config.keeper_bounty(distributable)?;
+                let executor_bounty = if capsule.executed_by != Pubkey::default() {
+                    bounty.min(execution_fee)
+                } else {
+                    0
+                };
+                let distributor_bounty = bounty.min(execution_fee - executor_bounty);
+                let mut platform_fee = execution_fee - executor_bounty - distributor_bounty;
+                let mut executor_bounty = executor_bounty;
+                if executor_bounty > 0 && !is_spl {
+                    // executed_by is fixed, so a SOL credit that leaves a drained executor below rent exemption
+                    // would fail every distribution attempt; the platform keeps that bounty instead
+                    let executor = ctx.accounts.executor.as_ref().ok_or(ErrorCode::InvalidKeeper)?;
+                    let rent_minimum = Rent::get()?.minimum_balance(executor.data_len());
+                    if executor.lamports().saturating_add(executor_bounty) < rent_minimum {
+                        msg!("Executor {:?} would stay below rent exemption, bounty goes to the platform", executor.key());
+                        platform_fee += executor_bounty;
+                        executor_bounty = 0;
+                    }
+                }
+
+                let vault_info = ctx.accounts.vault.to_account_info();
+                let vault_ata = if is_spl {
+                    Some(ctx.accounts.vault_token_account.as_deref().ok_or(ErrorCode::InvalidTokenAccount)?)
                 } else {
-                    **ctx.accounts.vault.to_account_info().try_borrow_mut_lamports()? -= execution_fee;
-                    **platform_recipient.to_account_info().try_borrow_mut_lamports()? += execution_fee;
+                    None
+                };
+                let token_program = ctx.accounts.token_program.to_account_info();
+                // SPL fees go to the fee recipient's ATA for capsule.mint (init_if_needed on DistributeAssets creates it)
+                pay_from_vault(
+                    &vault_info,
+                    vault_ata,
+                    &token_program,
+                    platform_recipient,
+                    ctx.accounts.platform_fee_token_account.as_deref(),
+                    platform_fee,
+                    signer_seeds,
+                )?;
+                if executor_bounty > 0 {
+                    let executor = ctx.accounts.executor.as_ref().ok_or(ErrorCode::InvalidKeeper)?;
+                    require!(executor.key() == capsule.executed_by, ErrorCode::InvalidKeeper);
+                    pay_from_vault(
+                        &vault_info,
+                        vault_ata,
+                        &token_program,
+                        executor,
+                        ctx.accounts.executor_token_account.as_deref(),
+                        executor_bounty,
+                        signer_seeds,
+                    )?;
+                    msg!("Keeper bounty {} paid to executor {:?}", executor_bounty, executor.key());
+                    emit!(FeeCharged {
+                        capsule: capsule.key(),
+                        owner: capsule.owner,
+                        kind: FeeKind::ExecutorBounty,
+                        recipient: executor.key(),
+                        mint: capsule.mint,
+                        amount: executor_bounty,
+                    });
+                }
+                pay_from_vault(
+                    &vault_info,
+                    vault_ata,
+                    &token_program,
+                    &ctx.accounts.payer.to_account_info(),
+                    ctx.accounts.keeper_token_account.as_deref(),
+                    distributor_bounty,
+                    signer_seeds,
+                )?;
+                msg!("Execution fee {} sent to platform, keeper bounty {} to {:?}", platform_fee, distributor_bounty, ctx.accounts.payer.key());
+                if platform_fee > 0 {
+                    emit!(FeeCharged {
+                        capsule: capsule.key(),
+                        owner: capsule.owner,
+                        kind: FeeKind::Execution,
+                        recipient: platform_recipient.key(),
+                        mint: capsule.mint,
+                        amount: platform_fee,
+                    });
+                }
+                if distributor_bounty > 0 {
+                    emit!(FeeCharged {
+                        capsule: capsule.key(),
+                        owner: capsule.owner,
+                        kind: FeeKind::DistributorBounty,
+                        recipient: ctx.accounts.payer.key(),
+                        mint: capsule.mint,
+                        amount: distributor_bounty,
+                    });
                 }
-                remaining_for_beneficiaries = total_amount_lamports.saturating_sub(execution_fee);
-                msg!("Execution fee {} sent to platform", execution_fee);
             }
         }
         
         // Distribute to beneficiaries
-        let total_for_ratio = total_amount_lamports;
         let mut distributed: u64 = 0;
-        let beneficiary_count = beneficiaries.len();
         
-        for (idx, beneficiary) in beneficiaries.iter().enumerate() {
-            let address_str = beneficiary.get("address")
-                .and_then(|a| a.as_str())
-                .ok_or(ErrorCode::InvalidIntentData)?;
-            
-            let beneficiary_pubkey = address_str.parse::<Pubkey>()
-                .map_err(|_| ErrorCode::InvalidBeneficiaryAddress)?;
-            
-            let amount_str = beneficiary.get("amount")
-                .and_then(|a| a.as_str())
-                .ok_or(ErrorCode::InvalidIntentData)?;
-            
-            let amount_type = beneficiary.get("amountType")
-                .and_then(|t| t.as_str())
-                .unwrap_or("fixed");
-            
-            let amount_lamports = if amount_type == "percentage" {
-                let percentage = amount_str.parse::<f64>()
-                    .map_err(|_| ErrorCode::InvalidIntentData)?;
-                (total_amount_lamports as f64 * percentage / 100.0) as u64
-            } else {
-                parse_sol_to_lamports(amount_str)
-                    .map_err(|_| ErrorCode::InvalidIntentData)?
-            };
-            
-            let to_send = if total_for_ratio == 0 {
-                0u64
-            } else if idx == beneficiary_count.saturating_sub(1) {
-                remaining_for_beneficiaries.saturating_sub(distributed)
-            } else {
-                (amount_lamports as u64)
-                    .checked_mul(remaining_for_beneficiaries)
-                    .and_then(|v| v.checked_div(total_for_ratio))
-                    .unwrap_or(0)
-            };
-            distributed = distributed.saturating_add(to_send);
+        for (idx, (beneficiary, to_send)) in intent.beneficiaries.iter().zip(shares).enumerate() {
+            let beneficiary_pubkey = beneficiary.address;
+            distributed = distributed
+                .checked_add(to_send)
+                .ok_or(ErrorCode::InsufficientVaultBalance)?;
             
             if to_send > 0 {
+                // SPL payouts go to the beneficiary's ATA for capsule.mint, SOL payouts to the wallet itself
+                let destination = if is_spl {
+                    get_associated_token_address(&beneficiary_pubkey, &capsule.mint)
+                } else {
+                    beneficiary_pubkey
+                };
                 let beneficiary_account = ctx.remaining_accounts
                     .iter()
-                    .find(|acc| acc.key() == beneficiary_pubkey)
-                    .ok_or(ErrorCode::InvalidBeneficiaryAddress)?;
+                    .find(|acc| acc.key() == destination)
+                    .ok_or_else(|| {
+                        msg!("Beneficiary {} ({}): account {} not passed in remaining accounts", idx, beneficiary_pubkey, destination);
+                        ErrorCode::MissingBeneficiaryAccount
+                    })?;
                 
                 if is_spl {
                      let vault_ata = ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
@@ -340,50 +701,213 @@ pub mod heres_program {
                      let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
                      token::transfer(cpi_ctx, to_send)?;
                 } else {
-                    **ctx.accounts.vault.to_account_info().try_borrow_mut_lamports()? -= to_send;
-                    **beneficiary_account.to_account_info().try_borrow_mut_lamports()? += to_send;
+                    transfer_lamports(&ctx.accounts.vault.to_account_info(), beneficiary_account, to_send)?;
                 }
-                msg!("Transferred {} to beneficiary: {}", to_send, beneficiary_pubkey);
+                msg!("Beneficiary {} ({}): transferred {}", idx, beneficiary_pubkey, to_send);
+                emit!(BeneficiaryPaid {
+                    capsule: capsule.key(),
+                    owner: capsule.owner,
+                    beneficiary: beneficiary_pubkey,
+                    index: idx as u8,
+                    mint: capsule.mint,
+                    amount: to_send,
+                });
             }
         }
+
+        let distributed_at = Clock::get()?.unix_timestamp;
+        ctx.accounts.capsule.distributed_at = Some(distributed_at);
+        emit!(AssetsDistributed {
+            capsule: ctx.accounts.capsule.key(),
+            owner: ctx.accounts.capsule.owner,
+            mint: ctx.accounts.capsule.mint,
+            amount: distributed,
+            distributed_at,
+        });
         
         Ok(())
     }
 
-    /// Update last activity timestamp (called by Helius webhook or user)
+    /// Close a fully distributed capsule, its vault and vault ATA. Anyone can call.
+    /// Rent goes to the owner's designated rent recipient, or to the last beneficiary when none was set.
+    /// A capsule with a scheduled crank task can't be closed until cancel_scheduled_task runs.
+    pub fn close_capsule(ctx: Context<CloseCapsule>) -> Result<()> {
+        let capsule = &ctx.accounts.capsule;
+        require!(capsule.distributed_at.is_some(), ErrorCode::CapsuleNotDistributed);
+        require!(capsule.crank_task_id.is_none(), ErrorCode::CrankTaskScheduled);
+
+        let expected_recipient = match capsule.designated_rent_recipient() {
+            Some(recipient) => recipient,
+            None => last_beneficiary(&ctx.accounts.intent_data.data)?,
+        };
+        require!(ctx.accounts.rent_recipient.key() == expected_recipient, ErrorCode::InvalidRentRecipient);
+
+        if capsule.mint != Pubkey::default() {
+            let mint = ctx.accounts.mint.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+            require!(mint.key() == capsule.mint, ErrorCode::InvalidTokenAccount);
+            let vault_ata = ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+            require!(vault_ata.amount == 0, ErrorCode::VaultNotEmpty);
+
+            let owner_key = capsule.owner;
+            let vault_seeds: &[&[u8]] = &[
+                b"capsule_vault",
+                owner_key.as_ref(),
+                &[capsule.vault_bump],
+            ];
+            let signer_seeds = &[vault_seeds];
+            let cpi_accounts = CloseAccount {
+                account: vault_ata.to_account_info(),
+                destination: ctx.accounts.rent_recipient.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token::close_account(cpi_ctx)?;
+        }
+
+        msg!("Capsule {:?} closed; rent returned to {:?}", capsule.key(), expected_recipient);
+        emit!(CapsuleClosed {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+            rent_recipient: expected_recipient,
+        });
+        Ok(())
+    }
+
+    /// Update last activity timestamp (owner signs; relayed proofs go through attest_activity)
     pub fn update_activity(ctx: Context<UpdateActivity>) -> Result<()> {
+        require!(ctx.accounts.capsule.owner == ctx.accounts.owner.key(), ErrorCode::Unauthorized);
+        ctx.accounts.capsule.last_activity = Clock::get()?.unix_timestamp;
+
+        // On the ER, restart the crank so its iterations cover the new inactivity window
+        if let Some(magic_program) = &ctx.accounts.magic_program {
+            if ctx.accounts.capsule.is_active && ctx.accounts.capsule.crank_task_id.is_some() {
+                let owner = ctx.accounts.owner.to_account_info();
+                let capsule_info = ctx.accounts.capsule.to_account_info();
+                let (interval, iterations) = (
+                    ctx.accounts.capsule.crank_interval_millis,
+                    ctx.accounts.capsule.crank_iterations,
+                );
+                let task_id = start_crank(
+                    magic_program,
+                    &owner,
+                    &capsule_info,
+                    &mut ctx.accounts.capsule,
+                    interval,
+                    iterations,
+                )?;
+                msg!("Rescheduled execute_intent crank: task_id={}", task_id);
+            }
+        }
+
+        msg!("Activity updated for capsule: {:?}", ctx.accounts.capsule.key());
+        emit!(ActivityUpdated {
+            capsule: ctx.accounts.capsule.key(),
+            owner: ctx.accounts.capsule.owner,
+            source: ActivitySource::Owner,
+            last_activity: ctx.accounts.capsule.last_activity,
+        });
+        Ok(())
+    }
+
+
+    /// Refresh last activity from an activity oracle attestation instead of the owner's signature.
+    /// The oracle watches the owner's on-chain transactions and signs
+    /// activity_attestation_message(capsule, activity_at); the transaction must carry that signature in an
+    /// Ed25519 program instruction immediately before this one. Anyone can relay it.
+    pub fn attest_activity(ctx: Context<AttestActivity>, activity_at: i64) -> Result<()> {
+        let oracle = ctx.accounts.program_config.activity_oracle;
+        require!(oracle != Pubkey::default(), ErrorCode::ActivityOracleNotSet);
+
+        let capsule_key = ctx.accounts.capsule.key();
+        verify_ed25519_signature(
+            &ctx.accounts.instructions_sysvar,
+            &oracle,
+            &activity_attestation_message(&capsule_key, activity_at),
+        )?;
+
         let capsule = &mut ctx.accounts.capsule;
-        require!(capsule.owner == ctx.accounts.owner.key(), ErrorCode::Unauthorized);
-        
+        require!(capsule.is_active, ErrorCode::CapsuleInactive);
+        require!(activity_at <= Clock::get()?.unix_timestamp, ErrorCode::InvalidActivityProof);
+        // Attestations can only move last_activity forward, so replaying one is a no-op error
+        require!(activity_at > capsule.last_activity, ErrorCode::StaleActivityProof);
+        capsule.last_activity = activity_at;
+
+        msg!("Activity attested for capsule {:?} at {}", capsule_key, activity_at);
+        emit!(ActivityUpdated {
+            capsule: capsule_key,
+            owner: capsule.owner,
+            source: ActivitySource::Oracle,
+            last_activity: activity_at,
+        });
+        Ok(())
+    }
+
+    /// Refresh last activity from a heartbeat the owner signed off-chain (heartbeat_message(capsule, nonce)).
+    /// The signature travels in an Ed25519 program instruction immediately before this one, so any relayer
+    /// can submit it and pay the fee. Nonces must strictly increase, which makes every heartbeat single-use.
+    pub fn update_activity_with_signature(ctx: Context<UpdateActivityWithSignature>, nonce: u64) -> Result<()> {
+        let capsule_key = ctx.accounts.capsule.key();
+        let owner = ctx.accounts.capsule.owner;
+        verify_ed25519_signature(
+            &ctx.accounts.instructions_sysvar,
+            &owner,
+            &heartbeat_message(&capsule_key, nonce),
+        )?;
+
+        let capsule = &mut ctx.accounts.capsule;
+        require!(capsule.is_active, ErrorCode::CapsuleInactive);
+        require!(nonce > capsule.activity_nonce, ErrorCode::StaleHeartbeatNonce);
+        capsule.activity_nonce = nonce;
         capsule.last_activity = Clock::get()?.unix_timestamp;
-        
-        msg!("Activity updated for capsule: {:?}", capsule.key());
+
+        msg!("Signed heartbeat for capsule {:?}: nonce={}", capsule_key, nonce);
+        emit!(ActivityUpdated {
+            capsule: capsule_key,
+            owner,
+            source: ActivitySource::SignedHeartbeat,
+            last_activity: capsule.last_activity,
+        });
         Ok(())
     }
 
+    /// Set where the capsule and vault rent goes when they are closed after distribution (owner only).
+    /// Pubkey::default() clears it, in which case close_capsule refunds the last beneficiary.
+    pub fn set_rent_recipient(ctx: Context<SetRentRecipient>, rent_recipient: Pubkey) -> Result<()> {
+        let capsule = &mut ctx.accounts.capsule;
+        require!(capsule.owner == ctx.accounts.owner.key(), ErrorCode::Unauthorized);
+        capsule.rent_recipient = rent_recipient;
+        msg!("Rent recipient for capsule {:?} set to {:?}", capsule.key(), rent_recipient);
+        emit!(RentRecipientUpdated {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+            rent_recipient,
+        });
+        Ok(())
+    }
 
     /// Delegate capsule and vault PDAs to Magicblock ER/PER. When no validator is passed, defaults to TEE validator (PER).
+    /// A non-zero commit_frequency_ms makes the ER commit periodically, so heartbeats (last_activity,
+    /// retry_count) become visible on L1 without undelegating.
     /// The #[delegate] macro handles this automatically for all fields marked with 'del'.
-    pub fn delegate_capsule(ctx: Context<DelegateCapsuleInput>) -> Result<()> {
-        let validator_key = ctx.accounts.validator
-            .as_ref()
-            .map(|v| v.key())
-            .unwrap_or(crate::TEE_VALIDATOR);
-
-        let config = DelegateConfig {
-            commit_frequency_ms: 0,
-            validator: Some(validator_key),
-        };
+    pub fn delegate_capsule(ctx: Context<DelegateCapsuleInput>, args: DelegateCapsuleArgs) -> Result<()> {
+        let validator_key = args.validator.unwrap_or(crate::TEE_VALIDATOR);
+        require!(ctx.accounts.program_config.is_validator_allowed(&validator_key), ErrorCode::ValidatorNotAllowed);
+        require!(
+            args.commit_frequency_ms == 0 || args.commit_frequency_ms >= MIN_COMMIT_FREQUENCY_MS,
+            ErrorCode::InvalidCommitFrequency
+        );
 
         msg!("Delegating capsule and vault to Ephemeral Rollup");
         let owner_key = ctx.accounts.owner.key();
+        record_delegation(&ctx.accounts.pda, validator_key, args.commit_frequency_ms)?;
 
         // Delegate Capsule PDA
         ctx.accounts.delegate_pda(
             &ctx.accounts.payer, 
             &[b"intent_capsule", owner_key.as_ref()], 
             DelegateConfig {
-                commit_frequency_ms: 0,
+                commit_frequency_ms: args.commit_frequency_ms,
                 validator: Some(validator_key),
             }
         )?;
@@ -393,73 +917,223 @@ pub mod heres_program {
             &ctx.accounts.payer, 
             &[b"capsule_vault", owner_key.as_ref()], 
             DelegateConfig {
-                commit_frequency_ms: 0,
+                commit_frequency_ms: args.commit_frequency_ms,
                 validator: Some(validator_key),
             }
         )?;
 
-        msg!("Capsule and Vault delegated to Ephemeral Rollup");
+        msg!("Capsule and Vault delegated to Ephemeral Rollup validator {:?}", validator_key);
+        emit!(CapsuleDelegated {
+            capsule: ctx.accounts.pda.key(),
+            owner: owner_key,
+            validator: validator_key,
+            commit_frequency_ms: args.commit_frequency_ms,
+        });
+        Ok(())
+    }
+
+    /// Start moving a delegated capsule to another allowlisted validator (ER only, owner only).
+    /// Records the target, then commits and undelegates capsule and vault; once the undelegation
+    /// lands on L1, anyone calls complete_migration to redelegate to the target.
+    pub fn migrate_delegation(ctx: Context<MigrateDelegation>, new_validator: Pubkey) -> Result<()> {
+        require!(ctx.accounts.program_config.is_validator_allowed(&new_validator), ErrorCode::ValidatorNotAllowed);
+        require!(
+            ctx.accounts.capsule.delegated_validator != new_validator,
+            ErrorCode::AlreadyDelegatedToValidator
+        );
+
+        ctx.accounts.capsule.pending_validator = new_validator;
+        // Persist before committing so the pending validator reaches L1 with the capsule
+        ctx.accounts.capsule.exit(&crate::ID)?;
+
+        let capsule_info = ctx.accounts.capsule.to_account_info();
+        let vault_info = ctx.accounts.vault.to_account_info();
+        commit_and_undelegate_accounts(
+            &ctx.accounts.owner.to_account_info(),
+            vec![&capsule_info, &vault_info],
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program.to_account_info(),
+        )?;
+
+        msg!("Migrating capsule {:?} from {:?} to {:?}", capsule_info.key(), ctx.accounts.capsule.delegated_validator, new_validator);
+        emit!(DelegationMigrationStarted {
+            capsule: capsule_info.key(),
+            owner: ctx.accounts.capsule.owner,
+            from_validator: ctx.accounts.capsule.delegated_validator,
+            to_validator: new_validator,
+        });
+        Ok(())
+    }
+
+    /// Redelegate a capsule undelegated by migrate_delegation to its pending validator (L1, anyone can call).
+    /// Keeps the commit frequency chosen at delegation.
+    pub fn complete_migration(ctx: Context<CompleteMigration>) -> Result<()> {
+        let (previous_validator, pending_validator, commit_frequency_ms) = {
+            let data = ctx.accounts.pda.try_borrow_data()?;
+            let capsule = IntentCapsule::try_deserialize(&mut &data[..])?;
+            (capsule.delegated_validator, capsule.pending_validator, capsule.commit_frequency_ms)
+        };
+        require!(pending_validator != Pubkey::default(), ErrorCode::NoPendingMigration);
+        require!(ctx.accounts.program_config.is_validator_allowed(&pending_validator), ErrorCode::ValidatorNotAllowed);
+
+        let owner_key = ctx.accounts.owner.key();
+        record_delegation(&ctx.accounts.pda, pending_validator, commit_frequency_ms)?;
+
+        ctx.accounts.delegate_pda(
+            &ctx.accounts.payer,
+            &[b"intent_capsule", owner_key.as_ref()],
+            DelegateConfig {
+                commit_frequency_ms,
+                validator: Some(pending_validator),
+            }
+        )?;
+        ctx.accounts.delegate_vault(
+            &ctx.accounts.payer,
+            &[b"capsule_vault", owner_key.as_ref()],
+            DelegateConfig {
+                commit_frequency_ms,
+                validator: Some(pending_validator),
+            }
+        )?;
+
+        msg!("Capsule migrated to validator {:?}", pending_validator);
+        emit!(CapsuleDelegated {
+            capsule: ctx.accounts.pda.key(),
+            owner: owner_key,
+            validator: pending_validator,
+            commit_frequency_ms,
+        });
+        emit!(DelegationMigrationCompleted {
+            capsule: ctx.accounts.pda.key(),
+            owner: owner_key,
+            from_validator: previous_validator,
+            to_validator: pending_validator,
+        });
         Ok(())
     }
 
 
-    /// Schedule crank to run execute_intent at intervals (Magicblock ScheduleTask).
-    /// Anyone can execute when conditions are met; this registers the task for the crank.
+    /// Schedule the execute_intent crank on the ER (owner only). The task id is derived from the
+    /// capsule, and any task already scheduled for it is cancelled first.
     pub fn schedule_execute_intent(
         ctx: Context<ScheduleExecuteIntent>,
         args: ScheduleExecuteIntentArgs,
     ) -> Result<()> {
+        require!(ctx.accounts.capsule.is_active, ErrorCode::CapsuleInactive);
         msg!("Scheduling execute_intent on TEE for capsule: {:?}", ctx.accounts.capsule.key());
 
+        let magic_program = ctx.accounts.magic_program.to_account_info();
+        let payer = ctx.accounts.payer.to_account_info();
+        let capsule_info = ctx.accounts.capsule.to_account_info();
+        let task_id = start_crank(
+            &magic_program,
+            &payer,
+            &capsule_info,
+            &mut ctx.accounts.capsule,
+            args.execution_interval_millis,
+            args.iterations,
+        )?;
 
-        // CRITICAL: Only include accounts that execute_intent actually needs
-        // execute_intent only updates capsule.is_active and capsule.executed_at
-        // It does NOT touch vault, permission, or any other accounts
-        // Including unnecessary accounts causes "account not delegated" errors on TEE
-        let accounts = vec![
-            AccountMeta::new(ctx.accounts.capsule.key(), false),
-            AccountMeta::new_readonly(ctx.accounts.vault.key(), false),
-            AccountMeta::new_readonly(ctx.accounts.permission_program.key(), false),
-            AccountMeta::new_readonly(ctx.accounts.permission.key(), false),
-        ];
+        msg!("Scheduled execute_intent crank: task_id={}", task_id);
+        Ok(())
+    }
 
-        let execute_ix = Instruction {
-            program_id: crate::ID,
-            accounts,
-            data: EXECUTE_INTENT_DISCRIMINATOR.to_vec(),
-        };
+    /// Cancel the capsule's scheduled execute_intent crank (owner only, never paused)
+    pub fn cancel_scheduled_task(ctx: Context<CancelScheduledTask>) -> Result<()> {
+        let task_id = ctx.accounts.capsule.crank_task_id.ok_or(ErrorCode::NoScheduledTask)?;
+        cancel_crank(
+            &ctx.accounts.magic_program.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.capsule.to_account_info(),
+            task_id,
+        )?;
+        ctx.accounts.capsule.crank_task_id = None;
 
-        let ix_data = bincode::serialize(&MagicBlockInstruction::ScheduleTask(ScheduleTaskArgs {
-            task_id: args.task_id,
-            execution_interval_millis: args.execution_interval_millis,
-            iterations: args.iterations,
-            instructions: vec![execute_ix],
-        }))
-        .map_err(|e| {
-            msg!("ERROR: failed to serialize ScheduleTask args: {:?}", e);
-            ErrorCode::InvalidInstructionData
-        })?;
+        msg!("Cancelled execute_intent crank: task_id={}", task_id);
+        emit!(CrankCancelled {
+            capsule: ctx.accounts.capsule.key(),
+            owner: ctx.accounts.capsule.owner,
+            task_id,
+        });
+        Ok(())
+    }
 
-        let schedule_ix = Instruction::new_with_bytes(
-            MAGIC_PROGRAM_ID,
-            &ix_data,
-            vec![
-                AccountMeta::new(ctx.accounts.payer.key(), true),
-                AccountMeta::new(ctx.accounts.capsule.key(), false),
-            ],
-        );
+    /// Bring a delegated capsule and its vault back to L1 (ER only, owner only, never paused).
+    /// Works on active capsules so the owner can always reach cancel_capsule; cancels the crank task first.
+    pub fn undelegate_capsule(ctx: Context<UndelegateCapsule>) -> Result<()> {
+        if let Some(task_id) = ctx.accounts.capsule.crank_task_id {
+            cancel_crank(
+                &ctx.accounts.magic_program.to_account_info(),
+                &ctx.accounts.owner.to_account_info(),
+                &ctx.accounts.capsule.to_account_info(),
+                task_id,
+            )?;
+            ctx.accounts.capsule.crank_task_id = None;
+            emit!(CrankCancelled {
+                capsule: ctx.accounts.capsule.key(),
+                owner: ctx.accounts.capsule.owner,
+                task_id,
+            });
+        }
+        // An undelegated capsule has no pending migration to complete
+        ctx.accounts.capsule.pending_validator = Pubkey::default();
+        // Persist before committing so the cleared task reaches L1 with the capsule
+        ctx.accounts.capsule.exit(&crate::ID)?;
+
+        let capsule_info = ctx.accounts.capsule.to_account_info();
+        let vault_info = ctx.accounts.vault.to_account_info();
+        commit_and_undelegate_accounts(
+            &ctx.accounts.owner.to_account_info(),
+            vec![&capsule_info, &vault_info],
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program.to_account_info(),
+        )?;
+
+        msg!("Undelegating capsule {:?} from {:?}", capsule_info.key(), ctx.accounts.capsule.delegated_validator);
+        emit!(CapsuleUndelegated {
+            capsule: capsule_info.key(),
+            owner: ctx.accounts.capsule.owner,
+            validator: ctx.accounts.capsule.delegated_validator,
+        });
+        Ok(())
+    }
+
+    /// Commit an executed capsule and its vault to the base layer and undelegate them (ER only).
+    /// Anyone can call; the crank runs it right after execute_intent so distribute_assets can follow on L1.
+    pub fn commit_and_undelegate_capsule(ctx: Context<CommitAndUndelegateCapsule>) -> Result<()> {
+        let capsule = &ctx.accounts.capsule;
+        require!(!capsule.is_active, ErrorCode::CapsuleActive);
+        require!(capsule.executed_at.is_some(), ErrorCode::CapsuleNotExecuted);
 
+        // The capsule PDA pays for the commit so the crank needs no signer
+        let owner_key = capsule.owner;
+        let capsule_seeds: &[&[u8]] = &[
+            b"intent_capsule",
+            owner_key.as_ref(),
+            &[capsule.bump],
+        ];
+        let capsule_info = ctx.accounts.capsule.to_account_info();
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let magic_program = ctx.accounts.magic_program.to_account_info();
+        let commit_ix = create_schedule_commit_ix(
+            &capsule_info,
+            &[&capsule_info, &vault_info],
+            &ctx.accounts.magic_context,
+            &magic_program,
+            true,
+        );
         invoke_signed(
-            &schedule_ix,
+            &commit_ix,
             &[
-                ctx.accounts.magic_program.to_account_info(),
-                ctx.accounts.payer.to_account_info(),
-                ctx.accounts.capsule.to_account_info(),
+                capsule_info.clone(),
+                ctx.accounts.magic_context.clone(),
+                vault_info,
+                magic_program,
             ],
-            &[],
+            &[capsule_seeds],
         )?;
 
-        msg!("Scheduled execute_intent crank: task_id={}", args.task_id);
+        msg!("Committed and undelegated capsule: {:?}", capsule_info.key());
         Ok(())
     }
 
@@ -497,52 +1171,320 @@ pub mod heres_program {
         Ok(())
     }
 
-    /// Recreate a capsule from executed state (owner locks new SOL in vault)
+    /// Cancel an active (not yet executed) capsule and withdraw everything to the owner (owner only).
+    /// Closes the capsule and vault (SOL and rent go back to the owner); SPL tokens are sent to the owner's ATA.
+    /// Never paused, so funds can always get out. The capsule must be undelegated (owned by this program on L1)
+    /// with no crank task; undelegate_capsule on the ER does both.
+    pub fn cancel_capsule(ctx: Context<CancelCapsule>) -> Result<()> {
+        let capsule = &ctx.accounts.capsule;
+        require!(capsule.is_active, ErrorCode::CapsuleInactive);
+        require!(capsule.crank_task_id.is_none(), ErrorCode::CrankTaskScheduled);
+
+        if capsule.mint != Pubkey::default() {
+            let mint = ctx.accounts.mint.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+            require!(mint.key() == capsule.mint, ErrorCode::InvalidTokenAccount);
+            let vault_ata = ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+            let owner_ata = ctx.accounts.owner_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+
+            let owner_key = capsule.owner;
+            let vault_seeds: &[&[u8]] = &[
+                b"capsule_vault",
+                owner_key.as_ref(),
+                &[capsule.vault_bump],
+            ];
+            let signer_seeds = &[vault_seeds];
+
+            let amount = vault_ata.amount;
+            if amount > 0 {
+                let cpi_accounts = Transfer {
+                    from: vault_ata.to_account_info(),
+                    to: owner_ata.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+                token::transfer(cpi_ctx, amount)?;
+            }
+
+            let cpi_accounts = CloseAccount {
+                account: vault_ata.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+            token::close_account(cpi_ctx)?;
+            msg!("Returned {} tokens to owner", amount);
+        }
+
+        msg!("Capsule cancelled and closed: {:?}", capsule.key());
+        emit!(CapsuleCancelled {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+        });
+        Ok(())
+    }
+
+    /// Recreate a capsule after its previous cycle was executed and distributed.
+    /// Anything left in the vault from the previous cycle is returned to the owner before
+    /// the new amount (SOL or SPL, selected by the optional mint) is locked.
     pub fn recreate_capsule(
         ctx: Context<RecreateCapsule>,
         inactivity_period: i64,
         intent_data: Vec<u8>,
     ) -> Result<()> {
-        let capsule = &mut ctx.accounts.capsule;
+        let capsule = &ctx.accounts.capsule;
         require!(capsule.owner == ctx.accounts.owner.key(), ErrorCode::Unauthorized);
         require!(!capsule.is_active, ErrorCode::CapsuleActive);
         require!(capsule.executed_at.is_some(), ErrorCode::CapsuleNotExecuted);
-        
-        let total_amount_lamports = {
-            let intent_data_str = String::from_utf8(intent_data.clone())
-                .map_err(|_| ErrorCode::InvalidIntentData)?;
-            let intent_json: serde_json::Value = serde_json::from_str(&intent_data_str)
-                .map_err(|_| ErrorCode::InvalidIntentData)?;
-            let total_str = intent_json.get("totalAmount")
-                .and_then(|t| t.as_str())
-                .ok_or(ErrorCode::InvalidIntentData)?;
-            parse_sol_to_lamports(total_str).map_err(|_| ErrorCode::InvalidIntentData)?
-        };
-        
-        capsule.inactivity_period = inactivity_period;
-        capsule.last_activity = Clock::get()?.unix_timestamp;
-        capsule.intent_data = intent_data;
-        capsule.is_active = true;
-        capsule.executed_at = None;
-        
-        // Lock new SOL in vault (owner signs)
-        let cpi_accounts = system_program::Transfer {
-            from: ctx.accounts.owner.to_account_info(),
-            to: ctx.accounts.vault.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.system_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        system_program::transfer(cpi_ctx, total_amount_lamports)?;
-        msg!("Locked {} lamports in vault for recreated capsule {:?}", total_amount_lamports, capsule.key());
-        
-        Ok(())
-    }
+        require!(capsule.distributed_at.is_some(), ErrorCode::CapsuleNotDistributed);
 
+        let total_amount_lamports = validate_intent(&intent_data)?;
+
+        let owner_key = capsule.owner;
+        let previous_mint = capsule.mint;
+        let new_mint = ctx.accounts.mint.as_ref().map(|m| m.key()).unwrap_or_default();
+        let vault_seeds: &[&[u8]] = &[
+            b"capsule_vault",
+            owner_key.as_ref(),
+            &[capsule.vault_bump],
+        ];
+        let signer_seeds = &[vault_seeds];
+
+        // Return leftover SOL above the vault's rent-exempt minimum
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let rent_minimum = Rent::get()?.minimum_balance(vault_info.data_len());
+        let leftover_lamports = vault_info.lamports().saturating_sub(rent_minimum);
+        if leftover_lamports > 0 {
+            transfer_lamports(&vault_info, &ctx.accounts.owner.to_account_info(), leftover_lamports)?;
+            msg!("Returned {} leftover lamports to owner", leftover_lamports);
+        }
+
+        // Return leftover tokens from a previous SPL cycle
+        if previous_mint != Pubkey::default() {
+            if previous_mint == new_mint {
+                // Same mint: the vault ATA is reused, so refund into the owner's source account
+                let vault_ata = ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+                let owner_ata = ctx.accounts.source_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+                require!(owner_ata.owner == owner_key, ErrorCode::InvalidTokenAccount);
+                let amount = vault_ata.amount;
+                if amount > 0 {
+                    let cpi_accounts = Transfer {
+                        from: vault_ata.to_account_info(),
+                        to: owner_ata.to_account_info(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+                    token::transfer(cpi_ctx, amount)?;
+                    msg!("Returned {} leftover tokens to owner", amount);
+                }
+            } else {
+                // Mint changed: drain and close the old vault ATA
+                let old_vault_ata = ctx.accounts.previous_vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+                require!(
+                    old_vault_ata.key() == get_associated_token_address(&ctx.accounts.vault.key(), &previous_mint),
+                    ErrorCode::InvalidTokenAccount
+                );
+                let amount = old_vault_ata.amount;
+                if amount > 0 {
+                    let owner_ata = ctx.accounts.previous_owner_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+                    require!(
+                        owner_ata.owner == owner_key && owner_ata.mint == previous_mint,
+                        ErrorCode::InvalidTokenAccount
+                    );
+                    let cpi_accounts = Transfer {
+                        from: old_vault_ata.to_account_info(),
+                        to: owner_ata.to_account_info(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+                    token::transfer(cpi_ctx, amount)?;
+                    msg!("Returned {} leftover tokens to owner", amount);
+                }
+
+                let cpi_accounts = CloseAccount {
+                    account: old_vault_ata.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
+                token::close_account(cpi_ctx)?;
+                msg!("Closed previous vault token account for mint {:?}", previous_mint);
+            }
+        }
+
+        let fee_config = &ctx.accounts.fee_config;
+        if fee_config.creation_fee_lamports > 0 {
+            let platform_recipient = ctx.accounts.platform_fee_recipient.as_mut().ok_or(ErrorCode::InvalidFeeConfig)?;
+            require!(platform_recipient.key() == fee_config.fee_recipient, ErrorCode::InvalidFeeConfig);
+
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.owner.to_account_info(),
+                to: platform_recipient.clone(),
+            };
+            let cpi_program = ctx.accounts.system_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            system_program::transfer(cpi_ctx, fee_config.creation_fee_lamports)?;
+            msg!("Creation fee {} lamports sent to platform recipient: {:?}", fee_config.creation_fee_lamports, platform_recipient.key());
+            emit!(FeeCharged {
+                capsule: ctx.accounts.capsule.key(),
+                owner: ctx.accounts.owner.key(),
+                kind: FeeKind::Creation,
+                recipient: platform_recipient.key(),
+                mint: Pubkey::default(),
+                amount: fee_config.creation_fee_lamports,
+            });
+        }
+
+        let capsule = &mut ctx.accounts.capsule;
+        capsule.inactivity_period = inactivity_period;
+        capsule.last_activity = Clock::get()?.unix_timestamp;
+        capsule.locked_amount = total_amount_lamports;
+        ctx.accounts.intent_data.data = intent_data;
+        capsule.is_active = true;
+        capsule.executed_at = None;
+        capsule.executed_by = Pubkey::default();
+        capsule.distributed_at = None;
+        capsule.crank_task_id = None;
+        capsule.mint = new_mint;
+
+        if new_mint != Pubkey::default() {
+            let from_ata = ctx.accounts.source_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+            let to_ata = ctx.accounts.vault_token_account.as_ref().ok_or(ErrorCode::InvalidTokenAccount)?;
+
+            let cpi_accounts = Transfer {
+                from: from_ata.to_account_info(),
+                to: to_ata.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, total_amount_lamports)?;
+            msg!("Locked {} tokens in vault for recreated capsule {:?}", total_amount_lamports, ctx.accounts.capsule.key());
+        } else {
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.owner.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.system_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            system_program::transfer(cpi_ctx, total_amount_lamports)?;
+            msg!("Locked {} lamports in vault for recreated capsule {:?}", total_amount_lamports, ctx.accounts.capsule.key());
+        }
+
+        let capsule = &ctx.accounts.capsule;
+        emit!(CapsuleRecreated {
+            capsule: capsule.key(),
+            owner: capsule.owner,
+            mint: capsule.mint,
+            total_amount: total_amount_lamports,
+            inactivity_period: capsule.inactivity_period,
+            recreated_at: capsule.last_activity,
+        });
+        Ok(())
+    }
+
+    /// Rewrite a capsule stored in an older layout as the current IntentCapsule version. Field values are
+    /// preserved and new fields start at their defaults; an inline intent moves into the IntentData account.
+    /// The payer funds the IntentData account and any growth, rent freed by shrinking the capsule goes to
+    /// the owner. Anyone can pay for the migration; the capsule must not be delegated.
+    pub fn migrate_capsule(ctx: Context<MigrateCapsule>) -> Result<()> {
+        let capsule_info = ctx.accounts.capsule.to_account_info();
+        let mut migrated = {
+            let data = capsule_info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *IntentCapsule::DISCRIMINATOR,
+                ErrorCode::InvalidCapsuleLayout
+            );
+            // v0 accounts are identified by their size: parsing them as the current layout would read
+            // whatever stale intent bytes follow the v0 fields as new fields
+            let capsule: IntentCapsule = if data.len() == 8 + IntentCapsuleV0::LEN {
+                IntentCapsuleV0::deserialize(&mut &data[8..])
+                    .map_err(|_| ErrorCode::InvalidCapsuleLayout)?
+                    .into()
+            } else {
+                IntentCapsule::try_deserialize(&mut &data[..]).map_err(|_| ErrorCode::InvalidCapsuleLayout)?
+            };
+            require!(capsule.version < IntentCapsule::VERSION, ErrorCode::CapsuleAlreadyMigrated);
+            capsule
+        };
+        let from_version = migrated.version;
+
+        // Move the inline intent into its own account
+        let intent = &mut ctx.accounts.intent_data;
+        intent.capsule = capsule_info.key();
+        intent.bump = ctx.bumps.intent_data;
+        intent.data = std::mem::take(&mut migrated.intent_data);
+        // Older layouts did not record the deposit; an active capsule still holds its intent's totalAmount
+        if migrated.is_active {
+            migrated.locked_amount = parse_intent(&intent.data).map(|i| i.total_amount).unwrap_or(0);
+        }
+
+        // Resize to the current layout: the payer covers any growth, rent freed by shrinking goes to the owner
+        let new_len = 8 + IntentCapsule::LEN;
+        let rent_minimum = Rent::get()?.minimum_balance(new_len);
+        let rent_due = rent_minimum.saturating_sub(capsule_info.lamports());
+        if rent_due > 0 {
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: capsule_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+            system_program::transfer(cpi_ctx, rent_due)?;
+        }
+        capsule_info.resize(new_len)?;
+        let freed = capsule_info.lamports().saturating_sub(rent_minimum);
+        transfer_lamports(&capsule_info, &ctx.accounts.owner.to_account_info(), freed)?;
+
+        let capsule = IntentCapsule {
+            version: IntentCapsule::VERSION,
+            ..migrated
+        };
+        capsule.try_serialize(&mut &mut capsule_info.try_borrow_mut_data()?[..])?;
+
+        msg!(
+            "Capsule {:?} migrated from v{} to v{}",
+            capsule_info.key(),
+            from_version,
+            IntentCapsule::VERSION
+        );
+        emit!(CapsuleMigrated {
+            capsule: capsule_info.key(),
+            owner: capsule.owner,
+            from_version,
+            to_version: IntentCapsule::VERSION,
+        });
+        Ok(())
+    }
+
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetPauseFlagsArgs {
+    pub create: bool,
+    pub execute: bool,
+    pub distribute: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct DelegateCapsuleArgs {
+    /// How often the ER commits capsule and vault state to L1; 0 = only on undelegation
+    pub commit_frequency_ms: u32,
+    /// ER validator to delegate to; defaults to TEE_VALIDATOR
+    pub validator: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetKeeperBountyArgs {
+    pub lamports: u64,
+    pub bps: u16,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct ScheduleExecuteIntentArgs {
-    pub task_id: u64,
     pub execution_interval_millis: u64,
     pub iterations: u64,
 }
@@ -553,8 +1495,6 @@ pub struct DelegateCapsuleInput<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
     pub owner: Signer<'info>,
-    /// CHECK: Checked by the delegation program
-    pub validator: Option<AccountInfo<'info>>,
     /// CHECK: PDA to delegate (capsule); seeds: [b"intent_capsule", owner]
     #[account(mut, del, seeds = [b"intent_capsule", owner.key().as_ref()], bump)]
     pub pda: AccountInfo<'info>,
@@ -567,19 +1507,77 @@ pub struct DelegateCapsuleInput<'info> {
     pub delegation_program: AccountInfo<'info>,
     /// CHECK: System program
     pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_create @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
+}
+
+#[commit]
+#[derive(Accounts)]
+pub struct MigrateDelegation<'info> {
+    /// Pays for the commit
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", owner.key().as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    #[account(
+        mut,
+        seeds = [b"capsule_vault", owner.key().as_ref()],
+        bump = capsule.vault_bump
+    )]
+    pub vault: Box<Account<'info, CapsuleVault>>,
+
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+pub struct CompleteMigration<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Capsule owner; only used to derive the PDAs
+    pub owner: AccountInfo<'info>,
+    /// CHECK: PDA to delegate (capsule); seeds: [b"intent_capsule", owner]
+    #[account(mut, del, seeds = [b"intent_capsule", owner.key().as_ref()], bump)]
+    pub pda: AccountInfo<'info>,
+    /// CHECK: PDA to delegate (vault); seeds: [b"capsule_vault", owner]
+    #[account(mut, del, seeds = [b"capsule_vault", owner.key().as_ref()], bump)]
+    pub vault: AccountInfo<'info>,
+    /// CHECK: Delegation program
+    pub delegation_program: AccountInfo<'info>,
+    /// CHECK: System program
+    pub system_program: Program<'info, System>,
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
 }
 
 
 #[derive(Accounts)]
 pub struct ScheduleExecuteIntent<'info> {
     /// CHECK: Magic program for CPI (MagicBlock crank scheduler)
+    #[account(address = MAGIC_PROGRAM_ID)]
     pub magic_program: AccountInfo<'info>,
-    /// Payer who signs the schedule transaction (on PER/TEE RPC)
+    /// Capsule owner; pays for and is the authority of the scheduled task (on PER/TEE RPC)
     #[account(mut)]
     pub payer: Signer<'info>,
-    /// CHECK: Capsule PDA delegated to PER/ER.
-    #[account(mut)]
-    pub capsule: AccountInfo<'info>,
+    /// Capsule PDA delegated to PER/ER
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", capsule.owner.as_ref()],
+        bump = capsule.bump,
+        constraint = capsule.owner == payer.key() @ ErrorCode::Unauthorized
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
     /// CHECK: Vault PDA
     pub vault: AccountInfo<'info>,
     /// MagicBlock Permission Program
@@ -593,6 +1591,12 @@ pub struct ScheduleExecuteIntent<'info> {
         seeds::program = PERMISSION_PROGRAM_ID
     )]
     pub permission: AccountInfo<'info>,
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_execute @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
 }
 
 #[derive(Accounts)]
@@ -609,12 +1613,133 @@ pub struct FeeConfig {
     pub fee_recipient: Pubkey,
     pub creation_fee_lamports: u64,
     pub execution_fee_bps: u16, // basis points, 10000 = 100%
+    pub pending_authority: Option<Pubkey>, // set by propose_fee_authority, cleared on accept
+    pub fee_update_delay: i64,             // seconds a fee increase waits before it can be applied (0 = immediate)
+    pub pending_creation_fee_lamports: u64,
+    pub pending_execution_fee_bps: u16,
+    pub pending_fees_effective_at: Option<i64>,
+    pub pending_fee_update_delay: i64,
+    pub pending_delay_effective_at: Option<i64>,
+    pub keeper_bounty_lamports: u64, // per successful execute / distribute call; takes precedence over bps
+    pub keeper_bounty_bps: u16,      // of the vault balance, when keeper_bounty_lamports is 0
 }
 
 impl FeeConfig {
+    pub const LEN: usize = 32 + // authority
+        32 +                     // fee_recipient
+        8 +                      // creation_fee_lamports
+        2 +                      // execution_fee_bps
+        1 + 32 +                 // pending_authority (Option<Pubkey>)
+        8 +                      // fee_update_delay
+        8 +                      // pending_creation_fee_lamports
+        2 +                      // pending_execution_fee_bps
+        1 + 8 +                  // pending_fees_effective_at (Option<i64>)
+        8 +                      // pending_fee_update_delay
+        1 + 8 +                  // pending_delay_effective_at (Option<i64>)
+        8 +                      // keeper_bounty_lamports
+        2;                       // keeper_bounty_bps
+
+    /// Bounty for one successful keeper call, in the vault asset's smallest unit (before capping at the execution fee)
+    pub fn keeper_bounty(&self, vault_balance: u64) -> Result<u64> {
+        if self.keeper_bounty_lamports > 0 {
+            return Ok(self.keeper_bounty_lamports);
+        }
+        let bounty = (vault_balance as u128)
+            .checked_mul(self.keeper_bounty_bps as u128)
+            .and_then(|v| v.checked_div(10_000))
+            .ok_or(ErrorCode::InvalidFeeConfig)?;
+        Ok(bounty as u64)
+    }
+}
+
+/// Original FeeConfig layout, as written by the first deployment. Only read by migrate_fee_config.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FeeConfigV0 {
+    pub authority: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub creation_fee_lamports: u64,
+    pub execution_fee_bps: u16,
+}
+
+impl FeeConfigV0 {
     pub const LEN: usize = 32 + 32 + 8 + 2;
 }
 
+impl From<FeeConfigV0> for FeeConfig {
+    fn from(v0: FeeConfigV0) -> Self {
+        FeeConfig {
+            authority: v0.authority,
+            fee_recipient: v0.fee_recipient,
+            creation_fee_lamports: v0.creation_fee_lamports,
+            execution_fee_bps: v0.execution_fee_bps,
+            pending_authority: None,
+            fee_update_delay: 0,
+            pending_creation_fee_lamports: 0,
+            pending_execution_fee_bps: 0,
+            pending_fees_effective_at: None,
+            pending_fee_update_delay: 0,
+            pending_delay_effective_at: None,
+            keeper_bounty_lamports: 0,
+            keeper_bounty_bps: 0,
+        }
+    }
+}
+
+/// Admin-controlled circuit breaker, checked by every create / execute / distribute instruction.
+#[account]
+pub struct ProgramConfig {
+    pub authority: Pubkey,
+    pub pause_create: bool,     // create_capsule, recreate_capsule, delegate_capsule
+    pub pause_execute: bool,    // execute_intent, schedule_execute_intent
+    pub pause_distribute: bool, // distribute_assets
+    pub allowed_validators: Vec<Pubkey>, // ER validators capsules may be delegated to; empty = TEE_VALIDATOR only
+    pub activity_oracle: Pubkey,          // signer of attest_activity proofs; Pubkey::default() = disabled
+}
+
+impl ProgramConfig {
+    pub const LEN: usize = 32 + 1 + 1 + 1 +
+        4 + 32 * MAX_ALLOWED_VALIDATORS + // allowed_validators
+        32;                              // activity_oracle
+
+    pub fn is_validator_allowed(&self, validator: &Pubkey) -> bool {
+        if self.allowed_validators.is_empty() {
+            *validator == TEE_VALIDATOR
+        } else {
+            self.allowed_validators.contains(validator)
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitProgramConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProgramConfig::LEN,
+        seeds = [b"program_config"],
+        bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProgramConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"program_config"],
+        bump,
+        constraint = program_config.authority == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct InitFeeConfig<'info> {
     #[account(
@@ -646,6 +1771,45 @@ pub struct UpdateFeeConfig<'info> {
 }
 
 #[derive(Accounts)]
+pub struct MigrateFeeConfig<'info> {
+    /// CHECK: May still be in the original layout that Account<FeeConfig> cannot load; the discriminator,
+    /// layout and authority are checked in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_config"],
+        bump,
+        owner = crate::ID @ ErrorCode::InvalidFeeConfig
+    )]
+    pub fee_config: UncheckedAccount<'info>,
+
+    /// Fee authority stored in the original layout; pays for the added fields
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptFeeAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_config"],
+        bump,
+        constraint = fee_config.pending_authority == Some(new_authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub fee_config: Account<'info, FeeConfig>,
+
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyFeeUpdate<'info> {
+    #[account(mut, seeds = [b"fee_config"], bump)]
+    pub fee_config: Account<'info, FeeConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(inactivity_period: i64, intent: Vec<u8>)]
 pub struct CreateCapsule<'info> {
     #[account(
         init,
@@ -664,6 +1828,15 @@ pub struct CreateCapsule<'info> {
         bump
     )]
     pub vault: Box<Account<'info, CapsuleVault>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = IntentData::space(intent.len()),
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
     
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -695,9 +1868,17 @@ pub struct CreateCapsule<'info> {
     
     
     pub associated_token_program: Program<'info, AssociatedToken>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_create @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
 }
 
 #[derive(Accounts)]
+#[instruction(new_intent: Vec<u8>)]
 pub struct UpdateIntent<'info> {
     #[account(
         mut,
@@ -705,6 +1886,32 @@ pub struct UpdateIntent<'info> {
         bump = capsule.bump
     )]
     pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    /// Resized to the new intent; the owner pays for growth and is refunded when it shrinks
+    #[account(
+        mut,
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump = intent_data.bump,
+        realloc = IntentData::space(new_intent.len()),
+        realloc::payer = owner,
+        realloc::zero = false
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetRentRecipient<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", owner.key().as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
     
     pub owner: Signer<'info>,
 }
@@ -737,11 +1944,22 @@ pub struct ExecuteIntent<'info> {
         seeds::program = PERMISSION_PROGRAM_ID
     )]
     pub permission: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_execute @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
+
+    /// Keeper triggering execution; paid the keeper bounty at distribution. The crank runs without one.
+    pub keeper: Option<Signer<'info>>,
 }
 
 #[derive(Accounts)]
 pub struct DistributeAssets<'info> {
     #[account(
+        mut,
         seeds = [b"intent_capsule", capsule.owner.as_ref()],
         bump = capsule.bump
     )]
@@ -753,6 +1971,12 @@ pub struct DistributeAssets<'info> {
         bump = capsule.vault_bump
     )]
     pub vault: Box<Account<'info, CapsuleVault>>,
+
+    #[account(
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump = intent_data.bump
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
     
     pub system_program: Program<'info, System>,
     
@@ -762,13 +1986,112 @@ pub struct DistributeAssets<'info> {
     pub fee_config: Box<Account<'info, FeeConfig>>,
     
     /// Platform fee recipient
+    /// CHECK: validated against fee_config.fee_recipient in instruction
     #[account(mut)]
     pub platform_fee_recipient: Option<AccountInfo<'info>>,
 
     pub mint: Option<Box<Account<'info, Mint>>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+    )]
     pub vault_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Fee recipient's ATA for capsule.mint (SPL capsules only; created if missing)
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = platform_fee_recipient,
+    )]
+    pub platform_fee_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Keeper's ATA for capsule.mint, receives the keeper bounty (SPL capsules only; created if missing)
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = payer,
+    )]
+    pub keeper_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Keeper that executed the capsule (must match capsule.executed_by when an executor bounty is due)
+    /// CHECK: validated against capsule.executed_by in instruction
+    #[account(mut)]
+    pub executor: Option<AccountInfo<'info>>,
+
+    /// Executor's ATA for capsule.mint (SPL capsules only; created if missing)
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = executor,
+    )]
+    pub executor_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Keeper triggering the distribution; receives the keeper bounty and pays for ATAs that have to be created
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_distribute @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseCapsule<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", capsule.owner.as_ref()],
+        bump = capsule.bump,
+        close = rent_recipient
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    #[account(
+        mut,
+        seeds = [b"capsule_vault", capsule.owner.as_ref()],
+        bump = capsule.vault_bump,
+        close = rent_recipient
+    )]
+    pub vault: Box<Account<'info, CapsuleVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump = intent_data.bump,
+        close = rent_recipient
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
+
+    /// CHECK: validated against the capsule's rent recipient (or last beneficiary) in instruction
+    #[account(mut)]
+    pub rent_recipient: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    pub mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+    )]
+    pub vault_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_distribute @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
 }
 
 #[derive(Accounts)]
@@ -780,7 +2103,135 @@ pub struct UpdateActivity<'info> {
     )]
     pub capsule: Box<Account<'info, IntentCapsule>>,
     
+    /// Pays for the rescheduled crank task
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Magic program (ER only); when passed, an active crank task is rescheduled
+    /// CHECK: Validated by address
+    #[account(address = MAGIC_PROGRAM_ID)]
+    pub magic_program: Option<AccountInfo<'info>>,
+}
+
+#[commit]
+#[derive(Accounts)]
+pub struct UndelegateCapsule<'info> {
+    /// Pays for the commit and signs as the crank task authority
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", owner.key().as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    #[account(
+        mut,
+        seeds = [b"capsule_vault", owner.key().as_ref()],
+        bump = capsule.vault_bump
+    )]
+    pub vault: Box<Account<'info, CapsuleVault>>,
+}
+
+#[commit]
+#[derive(Accounts)]
+pub struct CommitAndUndelegateCapsule<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", capsule.owner.as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    #[account(
+        mut,
+        seeds = [b"capsule_vault", capsule.owner.as_ref()],
+        bump = capsule.vault_bump
+    )]
+    pub vault: Box<Account<'info, CapsuleVault>>,
+}
+
+#[derive(Accounts)]
+pub struct AttestActivity<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", capsule.owner.as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
+
+    /// CHECK: Instructions sysvar, validated by address
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateCapsule<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Used to derive the capsule PDA; receives the rent freed by shrinking the capsule
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    /// CHECK: May still be in an old layout that Account<IntentCapsule> cannot load; the discriminator
+    /// and layout are checked in the handler
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", owner.key().as_ref()],
+        bump,
+        owner = crate::ID @ ErrorCode::InvalidCapsuleLayout
+    )]
+    pub capsule: UncheckedAccount<'info>,
+
+    /// Receives the intent that older layouts stored inline in the capsule
+    #[account(
+        init,
+        payer = payer,
+        space = IntentData::space(inline_intent_len(&capsule)),
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateActivityWithSignature<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", capsule.owner.as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    /// CHECK: Instructions sysvar, validated by address
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelScheduledTask<'info> {
+    /// CHECK: Magic program for CPI (MagicBlock crank scheduler)
+    #[account(address = MAGIC_PROGRAM_ID)]
+    pub magic_program: AccountInfo<'info>,
+
+    /// Task authority (the capsule owner)
+    #[account(mut)]
     pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", owner.key().as_ref()],
+        bump = capsule.bump
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
 }
 
 #[derive(Accounts)]
@@ -798,6 +2249,7 @@ pub struct RestartTimer<'info> {
 
 
 #[derive(Accounts)]
+#[instruction(inactivity_period: i64, intent: Vec<u8>)]
 pub struct RecreateCapsule<'info> {
     #[account(
         mut,
@@ -812,11 +2264,117 @@ pub struct RecreateCapsule<'info> {
         bump = capsule.vault_bump
     )]
     pub vault: Box<Account<'info, CapsuleVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump = intent_data.bump,
+        realloc = IntentData::space(intent.len()),
+        realloc::payer = owner,
+        realloc::zero = false
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
     
     #[account(mut)]
     pub owner: Signer<'info>,
     
+    #[account(seeds = [b"fee_config"], bump)]
+    pub fee_config: Box<Account<'info, FeeConfig>>,
+
+    /// Platform fee recipient (must match fee_config.fee_recipient when creation_fee_lamports > 0)
+    /// CHECK: validated against fee_config.fee_recipient in instruction
+    #[account(mut)]
+    pub platform_fee_recipient: Option<AccountInfo<'info>>,
+
     pub system_program: Program<'info, System>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Mint for the new cycle; omit to lock SOL
+    pub mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(mut)]
+    pub source_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+    )]
+    pub vault_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Vault ATA of the previous cycle's mint, required when the mint changes away from SPL
+    #[account(mut)]
+    pub previous_vault_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Owner's account for the previous mint, required when the old vault ATA still holds tokens
+    #[account(mut)]
+    pub previous_owner_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    #[account(
+        seeds = [b"program_config"],
+        bump,
+        constraint = !program_config.pause_create @ ErrorCode::ProgramPaused
+    )]
+    pub program_config: Box<Account<'info, ProgramConfig>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCapsule<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_capsule", owner.key().as_ref()],
+        bump = capsule.bump,
+        constraint = capsule.owner == owner.key() @ ErrorCode::Unauthorized,
+        close = owner
+    )]
+    pub capsule: Box<Account<'info, IntentCapsule>>,
+
+    #[account(
+        mut,
+        seeds = [b"capsule_vault", owner.key().as_ref()],
+        bump = capsule.vault_bump,
+        close = owner
+    )]
+    pub vault: Box<Account<'info, CapsuleVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"intent_data", capsule.key().as_ref()],
+        bump = intent_data.bump,
+        close = owner
+    )]
+    pub intent_data: Box<Account<'info, IntentData>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Program<'info, Token>,
+
+    pub mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+    )]
+    pub vault_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Owner's ATA for capsule.mint (SPL capsules only; created if missing)
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 
@@ -835,26 +2393,178 @@ pub struct IntentCapsule {
     pub owner: Pubkey,
     pub inactivity_period: i64, // seconds
     pub last_activity: i64,      // unix timestamp
-    pub intent_data: Vec<u8>,    // encoded intent instructions
+    pub intent_data: Vec<u8>,    // inline intent of v0/v1 layouts; empty from v2 on (see IntentData)
     pub is_active: bool,
     pub executed_at: Option<i64>,
     pub bump: u8,
     pub vault_bump: u8, // for invoke_signed when transferring from vault
     pub mint: Pubkey,
     pub retry_count: u64, // Fail-safe: track TEE/execution retries
+    pub rent_recipient: Pubkey, // receives capsule/vault rent on close; Pubkey::default() = last beneficiary
+    pub distributed_at: Option<i64>,
+    pub crank_task_id: Option<u64>, // active execute_intent crank on the ER
+    pub crank_interval_millis: u64,  // kept so the crank can be rescheduled
+    pub crank_iterations: u64,
+    pub crank_nonce: u64,            // bumped per schedule so every task gets a fresh id
+    pub executed_by: Pubkey,         // keeper that executed the capsule; Pubkey::default() = crank / none
+    pub delegated_validator: Pubkey, // ER validator the capsule was last delegated to
+    pub pending_validator: Pubkey,   // migrate_delegation target until complete_migration redelegates
+    pub commit_frequency_ms: u32,    // periodic ER -> L1 commits; 0 = only on undelegation
+    pub activity_nonce: u64,         // last nonce accepted by update_activity_with_signature
+    pub version: u8,                 // layout version; older accounts are upgraded by migrate_capsule
+    pub locked_amount: u64,          // totalAmount deposited by create/recreate; caps update_intent
+    pub reserved: [u8; 56],          // room for new fields without another realloc
 }
 
 impl IntentCapsule {
     pub const LEN: usize = 32 + // owner
         8 +                      // inactivity_period
         8 +                      // last_activity
-        4 + 1024 +               // intent_data (max 1KB)
+        4 +                      // intent_data (always empty; the intent lives in IntentData)
         1 +                      // is_active
         1 + 8 +                  // executed_at (Option<i64>)
         1 +                      // bump
         1 +                      // vault_bump
         32 +                     // mint
-        8;                       // retry_count
+        8 +                      // retry_count
+        32 +                     // rent_recipient
+        1 + 8 +                  // distributed_at (Option<i64>)
+        1 + 8 +                  // crank_task_id (Option<u64>)
+        8 +                      // crank_interval_millis
+        8 +                      // crank_iterations
+        8 +                      // crank_nonce
+        32 +                     // executed_by
+        32 +                     // delegated_validator
+        32 +                     // pending_validator
+        4 +                      // commit_frequency_ms
+        8 +                      // activity_nonce
+        1 +                      // version
+        8 +                      // locked_amount
+        56;                      // reserved
+
+    /// Current account layout. v0 is the original layout without a version byte; v0 and v1 keep the
+    /// intent inline, v2 moves it into the IntentData account.
+    pub const VERSION: u8 = 2;
+
+    /// Rent recipient chosen by the owner, if any
+    pub fn designated_rent_recipient(&self) -> Option<Pubkey> {
+        (self.rent_recipient != Pubkey::default()).then_some(self.rent_recipient)
+    }
+}
+
+/// Intent JSON of a capsule, stored next to it so it can grow past what fits in IntentCapsule.
+/// PDA: ["intent_data", capsule]. Resized on every write; the owner pays or is refunded the rent difference.
+#[account]
+pub struct IntentData {
+    pub capsule: Pubkey,
+    pub bump: u8,
+    pub data: Vec<u8>,
+}
+
+impl IntentData {
+    /// Account size (with discriminator) for an intent of `intent_len` bytes
+    pub const fn space(intent_len: usize) -> usize {
+        8 + 32 + 1 + 4 + intent_len
+    }
+}
+
+/// Original IntentCapsule layout (v0), as written by the first deployment. Only read by migrate_capsule.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct IntentCapsuleV0 {
+    pub owner: Pubkey,
+    pub inactivity_period: i64,
+    pub last_activity: i64,
+    pub intent_data: Vec<u8>,
+    pub is_active: bool,
+    pub executed_at: Option<i64>,
+    pub bump: u8,
+    pub vault_bump: u8,
+    pub mint: Pubkey,
+    pub retry_count: u64,
+}
+
+impl IntentCapsuleV0 {
+    pub const LEN: usize = 32 + 8 + 8 + 4 + 1024 + 1 + 1 + 8 + 1 + 1 + 32 + 8;
+}
+
+impl From<IntentCapsuleV0> for IntentCapsule {
+    fn from(v0: IntentCapsuleV0) -> Self {
+        IntentCapsule {
+            owner: v0.owner,
+            inactivity_period: v0.inactivity_period,
+            last_activity: v0.last_activity,
+            intent_data: v0.intent_data,
+            is_active: v0.is_active,
+            executed_at: v0.executed_at,
+            bump: v0.bump,
+            vault_bump: v0.vault_bump,
+            mint: v0.mint,
+            retry_count: v0.retry_count,
+            rent_recipient: Pubkey::default(),
+            // v0 never recorded distribution; distribute_assets on an already paid vault has nothing to send
+            distributed_at: None,
+            crank_task_id: None,
+            crank_interval_millis: 0,
+            crank_iterations: 0,
+            crank_nonce: 0,
+            executed_by: Pubkey::default(),
+            delegated_validator: Pubkey::default(),
+            pending_validator: Pubkey::default(),
+            commit_frequency_ms: 0,
+            activity_nonce: 0,
+            version: 0,
+            locked_amount: 0,
+            reserved: [0; 56],
+        }
+    }
+}
+
+// Capsule lifecycle events all start with the capsule and its owner. Amounts are in lamports, or in token
+// base units when `mint` is set (Pubkey::default() = SOL).
+
+#[event]
+pub struct CapsuleCreated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub total_amount: u64,
+    pub inactivity_period: i64,
+    pub created_at: i64,
+}
+
+#[event]
+pub struct IntentUpdated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub total_amount: u64,
+    pub intent_len: u32,
+    pub updated_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivitySource {
+    /// update_activity, signed by the owner
+    Owner,
+    /// attest_activity, signed by the activity oracle
+    Oracle,
+    /// update_activity_with_signature, owner-signed message relayed by anyone
+    SignedHeartbeat,
+}
+
+#[event]
+pub struct ActivityUpdated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub source: ActivitySource,
+    pub last_activity: i64,
+}
+
+#[event]
+pub struct TimerRestarted {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub retry_count: u64,
+    pub last_activity: i64,
 }
 
 #[event]
@@ -864,6 +2574,225 @@ pub struct IntentExecuted {
     pub executed_at: i64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeeKind {
+    /// Charged to the owner by create_capsule / recreate_capsule
+    Creation,
+    /// Platform share of the execution fee, taken from the vault by distribute_assets
+    Execution,
+    /// Keeper bounty for the keeper that executed the capsule
+    ExecutorBounty,
+    /// Keeper bounty for the keeper that distributed the capsule
+    DistributorBounty,
+}
+
+#[event]
+pub struct FeeCharged {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub kind: FeeKind,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BeneficiaryPaid {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub beneficiary: Pubkey,
+    pub index: u8, // position in the intent's beneficiaries
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct AssetsDistributed {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64, // paid to beneficiaries, after fees
+    pub distributed_at: i64,
+}
+
+#[event]
+pub struct CapsuleDelegated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub validator: Pubkey,
+    pub commit_frequency_ms: u32,
+}
+
+#[event]
+pub struct CapsuleUndelegated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    /// Validator the capsule was delegated to
+    pub validator: Pubkey,
+}
+
+#[event]
+pub struct DelegationMigrationStarted {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub from_validator: Pubkey,
+    pub to_validator: Pubkey,
+}
+
+#[event]
+pub struct DelegationMigrationCompleted {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub from_validator: Pubkey,
+    pub to_validator: Pubkey,
+}
+
+/// Emitted by schedule_execute_intent and when update_activity reschedules the crank on the ER
+#[event]
+pub struct CrankScheduled {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub task_id: u64,
+    /// Task cancelled to make room for this one
+    pub replaced_task_id: Option<u64>,
+    pub execution_interval_millis: u64,
+    pub iterations: u64,
+}
+
+#[event]
+pub struct CrankCancelled {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub task_id: u64,
+}
+
+#[event]
+pub struct RentRecipientUpdated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub rent_recipient: Pubkey,
+}
+
+#[event]
+pub struct CapsuleMigrated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct CapsuleRecreated {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub total_amount: u64,
+    pub inactivity_period: i64,
+    pub recreated_at: i64,
+}
+
+#[event]
+pub struct CapsuleCancelled {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct CapsuleClosed {
+    pub capsule: Pubkey,
+    pub owner: Pubkey,
+    pub rent_recipient: Pubkey,
+}
+
+#[event]
+pub struct FeeConfigInitialized {
+    pub authority: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub creation_fee_lamports: u64,
+    pub execution_fee_bps: u16,
+}
+
+#[event]
+pub struct FeeConfigUpdated {
+    pub creation_fee_lamports: u64,
+    pub execution_fee_bps: u16,
+}
+
+#[event]
+pub struct FeeConfigMigrated {
+    pub authority: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub creation_fee_lamports: u64,
+    pub execution_fee_bps: u16,
+}
+
+#[event]
+pub struct FeeUpdateQueued {
+    pub creation_fee_lamports: u64,
+    pub execution_fee_bps: u16,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct FeeUpdateDelayUpdated {
+    pub fee_update_delay: i64,
+}
+
+#[event]
+pub struct FeeUpdateDelayQueued {
+    pub fee_update_delay: i64,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct FeeAuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct FeeAuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct FeeRecipientUpdated {
+    pub previous_fee_recipient: Pubkey,
+    pub new_fee_recipient: Pubkey,
+}
+
+#[event]
+pub struct KeeperBountyUpdated {
+    pub keeper_bounty_lamports: u64,
+    pub keeper_bounty_bps: u16,
+}
+
+#[event]
+pub struct ProgramConfigInitialized {
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct AllowedValidatorsUpdated {
+    pub authority: Pubkey,
+    pub validators: Vec<Pubkey>,
+}
+
+#[event]
+pub struct ActivityOracleUpdated {
+    pub authority: Pubkey,
+    pub activity_oracle: Pubkey,
+}
+
+#[event]
+pub struct PauseFlagsUpdated {
+    pub authority: Pubkey,
+    pub pause_create: bool,
+    pub pause_execute: bool,
+    pub pause_distribute: bool,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Unauthorized: Only the owner can perform this action")]
@@ -876,6 +2805,7 @@ pub enum ErrorCode {
     CapsuleNotExecuted,
     #[msg("Inactivity period has not been met")]
     InactivityPeriodNotMet,
+    // No longer returned (see the specific intent errors below); kept so later error codes keep their numbers
     #[msg("Invalid intent data format")]
     InvalidIntentData,
     #[msg("Invalid beneficiary address")]
@@ -888,14 +2818,527 @@ pub enum ErrorCode {
     InvalidFeeConfig,
     #[msg("Invalid token account provided")]
     InvalidTokenAccount,
+    #[msg("No fee update is queued")]
+    NoPendingFeeUpdate,
+    #[msg("Queued fee update timelock has not elapsed")]
+    FeeUpdateNotReady,
+    #[msg("This operation is paused by the program admin")]
+    ProgramPaused,
+    #[msg("Vault balance is insufficient for this transfer")]
+    InsufficientVaultBalance,
+    #[msg("Lamport balance overflow")]
+    LamportOverflow,
+    #[msg("Capsule assets have already been distributed")]
+    AlreadyDistributed,
+    #[msg("Capsule assets have not been distributed")]
+    CapsuleNotDistributed,
+    #[msg("Rent recipient does not match the capsule's rent recipient")]
+    InvalidRentRecipient,
+    #[msg("Vault still holds tokens")]
+    VaultNotEmpty,
+    #[msg("No crank task is scheduled for this capsule")]
+    NoScheduledTask,
+    #[msg("Keeper account does not match the capsule's executor")]
+    InvalidKeeper,
+    #[msg("Validator is not on the program's allowlist")]
+    ValidatorNotAllowed,
+    #[msg("Too many validators for the allowlist")]
+    TooManyValidators,
+    #[msg("Capsule has no pending delegation migration")]
+    NoPendingMigration,
+    #[msg("Commit frequency is below the minimum")]
+    InvalidCommitFrequency,
+    #[msg("Missing or invalid Ed25519 signature instruction")]
+    InvalidSignature,
+    #[msg("No activity oracle is registered")]
+    ActivityOracleNotSet,
+    #[msg("Activity proof is in the future")]
+    InvalidActivityProof,
+    #[msg("Activity proof is not newer than the last recorded activity")]
+    StaleActivityProof,
+    #[msg("Heartbeat nonce must be greater than the last accepted nonce")]
+    StaleHeartbeatNonce,
+    #[msg("Account is not an IntentCapsule in a known layout")]
+    InvalidCapsuleLayout,
+    #[msg("Capsule is already at the current layout version")]
+    CapsuleAlreadyMigrated,
+    #[msg("Intent lists more beneficiaries than MAX_BENEFICIARIES")]
+    TooManyBeneficiaries,
+    #[msg("Intent has no totalAmount")]
+    MissingTotalAmount,
+    #[msg("Intent totalAmount is not a positive amount")]
+    InvalidTotalAmount,
+    #[msg("Intent has no beneficiaries")]
+    NoBeneficiaries,
+    #[msg("Beneficiary is listed more than once")]
+    DuplicateBeneficiary,
+    #[msg("Beneficiary amountType must be \"fixed\" or \"percentage\"")]
+    InvalidAmountType,
+    #[msg("Beneficiary percentage must be greater than 0 and at most 100")]
+    BadPercentage,
+    #[msg("Beneficiary fixed amount must be a positive amount")]
+    InvalidFixedAmount,
+    #[msg("Beneficiary percentages must sum to 100")]
+    PercentagesDoNotSumTo100,
+    #[msg("Beneficiary shares exceed totalAmount")]
+    SharesExceedTotal,
+    #[msg("Intent totalAmount exceeds the amount locked in the vault")]
+    TotalExceedsLockedAmount,
+    #[msg("Intent data is not valid UTF-8")]
+    InvalidIntentEncoding,
+    #[msg("Intent data is not valid JSON")]
+    InvalidIntentJson,
+    #[msg("Beneficiary payout account missing from remaining accounts")]
+    MissingBeneficiaryAccount,
+    #[msg("Intent data exceeds MAX_INTENT_DATA_LEN")]
+    IntentDataTooLarge,
+    #[msg("Fee config is already at the current layout")]
+    FeeConfigAlreadyMigrated,
+    #[msg("Capsule is already delegated to this validator")]
+    AlreadyDelegatedToValidator,
+    #[msg("Cancel the capsule's scheduled crank task first")]
+    CrankTaskScheduled,
 }
 
-/// Parse SOL amount string to lamports
-fn parse_sol_to_lamports(sol_str: &str) -> Result<u64> {
-    let sol_amount: f64 = sol_str.parse()
-        .map_err(|_| ErrorCode::InvalidIntentData)?;
-    
-    // Convert SOL to lamports (1 SOL = 1_000_000_000 lamports)
-    let lamports = (sol_amount * 1_000_000_000.0) as u64;
-    Ok(lamports)
+/// Crank task id for a capsule: first 8 bytes of sha256("execute_intent_task" || capsule || nonce)
+pub fn crank_task_id(capsule: &Pubkey, nonce: u64) -> u64 {
+    let hash = hashv(&[b"execute_intent_task", capsule.as_ref(), &nonce.to_le_bytes()]);
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&hash.to_bytes()[..8]);
+    u64::from_le_bytes(id)
+}
+
+/// execute_intent instruction run by the crank, built from the generated instruction and accounts
+/// types so it tracks the handler. Only includes accounts that execute_intent actually needs;
+/// unnecessary accounts cause "account not delegated" errors on TEE.
+pub fn execute_intent_crank_ix(capsule: &Pubkey, owner: &Pubkey, vault_bump: u8) -> Result<Instruction> {
+    let vault = vault_address(owner, vault_bump)?;
+    let (permission, _) = Pubkey::find_program_address(&[b"permission", capsule.as_ref()], &PERMISSION_PROGRAM_ID);
+    let (program_config, _) = Pubkey::find_program_address(&[b"program_config"], &crate::ID);
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts: accounts::ExecuteIntent {
+            capsule: *capsule,
+            vault,
+            permission_program: PERMISSION_PROGRAM_ID,
+            permission,
+            program_config,
+            keeper: None,
+        }
+        .to_account_metas(None),
+        data: instruction::ExecuteIntent {}.data(),
+    })
+}
+
+/// commit_and_undelegate_capsule instruction run by the crank after execute_intent. The task
+/// transaction fails as a whole while the inactivity period is not met, so nothing is committed early.
+pub fn commit_and_undelegate_crank_ix(capsule: &Pubkey, owner: &Pubkey, vault_bump: u8) -> Result<Instruction> {
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts: accounts::CommitAndUndelegateCapsule {
+            capsule: *capsule,
+            vault: vault_address(owner, vault_bump)?,
+            magic_program: MAGIC_PROGRAM_ID,
+            magic_context: MAGIC_CONTEXT_ID,
+        }
+        .to_account_metas(None),
+        data: instruction::CommitAndUndelegateCapsule {}.data(),
+    })
+}
+
+fn vault_address(owner: &Pubkey, vault_bump: u8) -> Result<Pubkey> {
+    Pubkey::create_program_address(&[b"capsule_vault", owner.as_ref(), &[vault_bump]], &crate::ID)
+        .map_err(|_| error!(ErrorCode::InvalidInstructionData))
+}
+
+/// Cancel any active crank task and schedule a fresh one; `authority` pays for and owns the task.
+/// Emits CrankScheduled, naming the task it replaced.
+fn start_crank<'info>(
+    magic_program: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    capsule_info: &AccountInfo<'info>,
+    capsule: &mut IntentCapsule,
+    execution_interval_millis: u64,
+    iterations: u64,
+) -> Result<u64> {
+    let replaced_task_id = capsule.crank_task_id.take();
+    if let Some(previous) = replaced_task_id {
+        cancel_crank(magic_program, authority, capsule_info, previous)?;
+        msg!("Cancelled previous crank task_id={}", previous);
+    }
+
+    let task_id = crank_task_id(capsule_info.key, capsule.crank_nonce);
+    let ix_data = bincode::serialize(&MagicBlockInstruction::ScheduleTask(ScheduleTaskArgs {
+        task_id,
+        execution_interval_millis,
+        iterations,
+        instructions: vec![
+            execute_intent_crank_ix(capsule_info.key, &capsule.owner, capsule.vault_bump)?,
+            commit_and_undelegate_crank_ix(capsule_info.key, &capsule.owner, capsule.vault_bump)?,
+        ],
+    }))
+    .map_err(|e| {
+        msg!("ERROR: failed to serialize ScheduleTask args: {:?}", e);
+        ErrorCode::InvalidInstructionData
+    })?;
+    let schedule_ix = Instruction::new_with_bytes(
+        MAGIC_PROGRAM_ID,
+        &ix_data,
+        vec![
+            AccountMeta::new(authority.key(), true),
+            AccountMeta::new(capsule_info.key(), false),
+        ],
+    );
+    invoke_signed(
+        &schedule_ix,
+        &[magic_program.clone(), authority.clone(), capsule_info.clone()],
+        &[],
+    )?;
+
+    capsule.crank_task_id = Some(task_id);
+    capsule.crank_interval_millis = execution_interval_millis;
+    capsule.crank_iterations = iterations;
+    capsule.crank_nonce = capsule.crank_nonce.checked_add(1).ok_or(ErrorCode::InvalidInstructionData)?;
+    emit!(CrankScheduled {
+        capsule: capsule_info.key(),
+        owner: capsule.owner,
+        task_id,
+        replaced_task_id,
+        execution_interval_millis,
+        iterations,
+    });
+    Ok(task_id)
+}
+
+fn cancel_crank<'info>(
+    magic_program: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    capsule_info: &AccountInfo<'info>,
+    task_id: u64,
+) -> Result<()> {
+    let ix_data = bincode::serialize(&MagicBlockInstruction::CancelTask { task_id })
+        .map_err(|_| ErrorCode::InvalidInstructionData)?;
+    let cancel_ix = Instruction::new_with_bytes(
+        MAGIC_PROGRAM_ID,
+        &ix_data,
+        vec![
+            AccountMeta::new(authority.key(), true),
+            AccountMeta::new(capsule_info.key(), false),
+        ],
+    );
+    invoke_signed(
+        &cancel_ix,
+        &[magic_program.clone(), authority.clone(), capsule_info.clone()],
+        &[],
+    )?;
+    Ok(())
+}
+
+/// Last beneficiary listed in the intent JSON (receives leftover rent when no rent recipient is set)
+fn last_beneficiary(intent_data: &[u8]) -> Result<Pubkey> {
+    parse_intent(intent_data)?
+        .beneficiaries
+        .last()
+        .map(|b| b.address)
+        .ok_or_else(|| ErrorCode::NoBeneficiaries.into())
+}
+
+/// How a beneficiary's share is expressed in the intent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntentShare {
+    /// Percent of totalAmount
+    Percentage(f64),
+    /// Amount in lamports (or token base units)
+    Fixed(u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntentBeneficiary {
+    pub address: Pubkey,
+    pub share: IntentShare,
+}
+
+impl IntentBeneficiary {
+    /// Share in lamports (or token base units) of an intent totalling `total_amount`
+    pub fn amount(&self, total_amount: u64) -> u64 {
+        match self.share {
+            IntentShare::Percentage(percentage) => (total_amount as f64 * percentage / 100.0) as u64,
+            IntentShare::Fixed(amount) => amount,
+        }
+    }
+}
+
+/// Execution fee on a vault holding `total`: `fee_bps` basis points (at most 100%), rounded down.
+/// Keeper bounties are paid out of it.
+pub fn execution_fee(total: u64, fee_bps: u16) -> u64 {
+    (total as u128 * fee_bps.min(10_000) as u128 / 10_000) as u64
+}
+
+/// Beneficiary payouts, in intent order, from a vault holding `total` after the execution fee.
+/// Each share is the beneficiary's amount scaled by `(total - fee) / intent.total_amount` and rounded down;
+/// the last beneficiary takes whatever is left, so the payouts and the fee always add up to `total`.
+/// Shares are computed in u128 and capped at what is left, so no input can overflow or overpay.
+pub fn compute_distribution(total: u64, fee_bps: u16, intent: &ParsedIntent) -> Vec<u64> {
+    let remaining = total - execution_fee(total, fee_bps);
+    let last = intent.beneficiaries.len().saturating_sub(1);
+    let mut distributed: u64 = 0;
+    intent
+        .beneficiaries
+        .iter()
+        .enumerate()
+        .map(|(idx, beneficiary)| {
+            let left = remaining - distributed;
+            let share = if idx == last {
+                left
+            } else if intent.total_amount == 0 {
+                0
+            } else {
+                let scaled = beneficiary.amount(intent.total_amount) as u128 * remaining as u128
+                    / intent.total_amount as u128;
+                scaled.min(left as u128) as u64
+            };
+            distributed += share;
+            share
+        })
+        .collect()
+}
+
+/// Intent JSON decoded into amounts and addresses
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedIntent {
+    pub total_amount: u64,
+    pub beneficiaries: Vec<IntentBeneficiary>,
+}
+
+/// Decode an intent JSON. Only the structure is checked (see validate_intent for the rules applied on write);
+/// every failure has its own error and logs the offending beneficiary index and value.
+pub fn parse_intent(intent_data: &[u8]) -> Result<ParsedIntent> {
+    let intent_str = std::str::from_utf8(intent_data).map_err(|e| {
+        msg!("Intent data is not UTF-8 (valid up to byte {})", e.valid_up_to());
+        ErrorCode::InvalidIntentEncoding
+    })?;
+    let intent_json: serde_json::Value = serde_json::from_str(intent_str).map_err(|e| {
+        msg!("Intent data is not valid JSON (line {}, column {})", e.line(), e.column());
+        ErrorCode::InvalidIntentJson
+    })?;
+
+    let total_str = intent_json.get("totalAmount").and_then(|t| t.as_str()).ok_or_else(|| {
+        msg!("Intent has no totalAmount string");
+        ErrorCode::MissingTotalAmount
+    })?;
+    let total_amount = parse_amount(total_str).ok_or_else(|| {
+        msg!("Intent totalAmount {:?} is not an amount", total_str);
+        ErrorCode::InvalidTotalAmount
+    })?;
+
+    let entries = intent_json.get("beneficiaries").and_then(|b| b.as_array()).ok_or_else(|| {
+        msg!("Intent has no beneficiaries array");
+        ErrorCode::NoBeneficiaries
+    })?;
+    let mut beneficiaries = Vec::with_capacity(entries.len());
+    for (idx, entry) in entries.iter().enumerate() {
+        let address_str = entry.get("address").and_then(|a| a.as_str()).unwrap_or_default();
+        let address = address_str.parse::<Pubkey>().map_err(|_| {
+            msg!("Beneficiary {}: address {:?} is not a valid pubkey", idx, address_str);
+            ErrorCode::InvalidBeneficiaryAddress
+        })?;
+
+        let amount_str = entry.get("amount").and_then(|a| a.as_str()).unwrap_or_default();
+        let share = match entry.get("amountType").and_then(|t| t.as_str()).unwrap_or("fixed") {
+            "percentage" => IntentShare::Percentage(amount_str.parse::<f64>().map_err(|_| {
+                msg!("Beneficiary {}: percentage {:?} is not a number", idx, amount_str);
+                ErrorCode::BadPercentage
+            })?),
+            "fixed" => IntentShare::Fixed(parse_amount(amount_str).ok_or_else(|| {
+                msg!("Beneficiary {}: fixed amount {:?} is not an amount", idx, amount_str);
+                ErrorCode::InvalidFixedAmount
+            })?),
+            other => {
+                msg!("Beneficiary {}: unknown amountType {:?}", idx, other);
+                return err!(ErrorCode::InvalidAmountType);
+            }
+        };
+        beneficiaries.push(IntentBeneficiary { address, share });
+    }
+
+    Ok(ParsedIntent { total_amount, beneficiaries })
+}
+
+/// Check an intent before it is written to IntentData and return its totalAmount.
+/// Beneficiaries must have distinct, valid addresses and a known amountType ("fixed" when omitted).
+/// Percentages must be in (0, 100]; an all-percentage intent must sum to exactly 100, and in any
+/// intent the shares together may not exceed totalAmount.
+pub fn validate_intent(intent_data: &[u8]) -> Result<u64> {
+    if intent_data.len() > MAX_INTENT_DATA_LEN {
+        msg!("Intent is {} bytes, limit is {}", intent_data.len(), MAX_INTENT_DATA_LEN);
+        return err!(ErrorCode::IntentDataTooLarge);
+    }
+    let intent = parse_intent(intent_data)?;
+    if intent.total_amount == 0 {
+        msg!("Intent totalAmount must be positive");
+        return err!(ErrorCode::InvalidTotalAmount);
+    }
+    if intent.beneficiaries.is_empty() {
+        msg!("Intent has no beneficiaries");
+        return err!(ErrorCode::NoBeneficiaries);
+    }
+    if intent.beneficiaries.len() > MAX_BENEFICIARIES {
+        msg!("Intent has {} beneficiaries, limit is {}", intent.beneficiaries.len(), MAX_BENEFICIARIES);
+        return err!(ErrorCode::TooManyBeneficiaries);
+    }
+
+    let mut percentage_sum = 0f64;
+    let mut has_fixed = false;
+    let mut shares: u64 = 0;
+    for (idx, beneficiary) in intent.beneficiaries.iter().enumerate() {
+        if let Some(first) = intent.beneficiaries[..idx].iter().position(|b| b.address == beneficiary.address) {
+            msg!("Beneficiary {}: {} is already listed at index {}", idx, beneficiary.address, first);
+            return err!(ErrorCode::DuplicateBeneficiary);
+        }
+        match beneficiary.share {
+            IntentShare::Percentage(percentage) => {
+                if !(percentage > 0.0 && percentage <= 100.0) {
+                    msg!("Beneficiary {}: percentage {} is outside (0, 100]", idx, percentage);
+                    return err!(ErrorCode::BadPercentage);
+                }
+                percentage_sum += percentage;
+            }
+            IntentShare::Fixed(amount) => {
+                if amount == 0 {
+                    msg!("Beneficiary {}: fixed amount must be positive", idx);
+                    return err!(ErrorCode::InvalidFixedAmount);
+                }
+                has_fixed = true;
+            }
+        }
+        shares = shares
+            .checked_add(beneficiary.amount(intent.total_amount))
+            .filter(|shares| *shares <= intent.total_amount)
+            .ok_or_else(|| {
+                msg!("Beneficiary {}: shares exceed totalAmount {}", idx, intent.total_amount);
+                ErrorCode::SharesExceedTotal
+            })?;
+    }
+
+    // Tolerance for decimal percentages such as 33.33 + 33.33 + 33.34
+    if !has_fixed && (percentage_sum - 100.0).abs() >= 1e-6 {
+        msg!("Beneficiary percentages sum to {}, expected 100", percentage_sum);
+        return err!(ErrorCode::PercentagesDoNotSumTo100);
+    }
+    Ok(intent.total_amount)
+}
+
+/// Decimal SOL (or token) amount in base units; None for negative, non-finite or unparsable amounts
+fn parse_amount(amount: &str) -> Option<u64> {
+    let value: f64 = amount.parse().ok()?;
+    let base_units = value * 1_000_000_000.0;
+    (value.is_finite() && base_units >= 0.0 && base_units < u64::MAX as f64).then_some(base_units as u64)
+}
+
+/// Length of the inline intent of an IntentCapsule account; 0 if it cannot be read.
+/// The intent Vec sits at the same offset in every layout (after discriminator, owner and two i64s).
+pub fn inline_intent_len(capsule: &AccountInfo) -> usize {
+    const OFFSET: usize = 8 + 32 + 8 + 8;
+    capsule
+        .try_borrow_data()
+        .ok()
+        .and_then(|data| data.get(OFFSET..OFFSET + 4).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize))
+        .unwrap_or(0)
+}
+
+/// Message an activity oracle signs to attest owner activity on `capsule` at `activity_at`
+pub fn activity_attestation_message(capsule: &Pubkey, activity_at: i64) -> Vec<u8> {
+    [b"heres:activity:".as_ref(), capsule.as_ref(), &activity_at.to_le_bytes()].concat()
+}
+
+/// Human-readable heartbeat an owner signs in their wallet; `nonce` must exceed the capsule's activity_nonce
+pub fn heartbeat_message(capsule: &Pubkey, nonce: u64) -> Vec<u8> {
+    format!("Heres heartbeat: I'm alive\ncapsule: {}\nnonce: {}", capsule, nonce).into_bytes()
+}
+
+/// Require that the instruction right before the current one is an Ed25519 program instruction
+/// verifying a single signature by `signer` over exactly `message`, with all data inline.
+fn verify_ed25519_signature(instructions: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+    let previous = current.checked_sub(1).ok_or(ErrorCode::InvalidSignature)?;
+    let ix = load_instruction_at_checked(previous as usize, instructions)?;
+    require!(ix.program_id == ed25519_program::ID, ErrorCode::InvalidSignature);
+
+    // Layout: [num_signatures, padding, offsets (7 x u16), ...data]
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, ErrorCode::InvalidSignature);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let signature_ix = read_u16(4);
+    let public_key_offset = read_u16(6) as usize;
+    let public_key_ix = read_u16(8);
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    let message_ix = read_u16(14);
+    // u16::MAX means "this instruction"; anything else could point the check at other data
+    require!(
+        signature_ix == u16::MAX && public_key_ix == u16::MAX && message_ix == u16::MAX,
+        ErrorCode::InvalidSignature
+    );
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(ErrorCode::InvalidSignature)?;
+    require!(public_key == signer.as_ref(), ErrorCode::InvalidSignature);
+    let signed_message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(ErrorCode::InvalidSignature)?;
+    require!(signed_message == message, ErrorCode::InvalidSignature);
+    Ok(())
+}
+
+/// Record delegation settings in the capsule before it is handed to the delegation program
+fn record_delegation(capsule_info: &AccountInfo, validator: Pubkey, commit_frequency_ms: u32) -> Result<()> {
+    let mut data = capsule_info.try_borrow_mut_data()?;
+    let mut capsule = IntentCapsule::try_deserialize(&mut &data[..])?;
+    capsule.delegated_validator = validator;
+    capsule.commit_frequency_ms = commit_frequency_ms;
+    capsule.pending_validator = Pubkey::default();
+    capsule.try_serialize(&mut &mut data[..])?;
+    Ok(())
+}
+
+/// Pay `amount` out of the vault: tokens to `token_account` for SPL capsules, lamports to `wallet` otherwise
+fn pay_from_vault<'info>(
+    vault: &AccountInfo<'info>,
+    vault_token_account: Option<&Account<'info, TokenAccount>>,
+    token_program: &AccountInfo<'info>,
+    wallet: &AccountInfo<'info>,
+    token_account: Option<&Account<'info, TokenAccount>>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    match vault_token_account {
+        Some(vault_ata) => {
+            let to = token_account.ok_or(ErrorCode::InvalidTokenAccount)?;
+            let cpi_accounts = Transfer {
+                from: vault_ata.to_account_info(),
+                to: to.to_account_info(),
+                authority: vault.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer_seeds);
+            token::transfer(cpi_ctx, amount)
+        }
+        None => transfer_lamports(vault, wallet, amount),
+    }
+}
+
+/// Move lamports out of a program-owned account, with checked arithmetic on both balances
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let from_balance = from.lamports()
+        .checked_sub(amount)
+        .ok_or(ErrorCode::InsufficientVaultBalance)?;
+    let to_balance = to.lamports()
+        .checked_add(amount)
+        .ok_or(ErrorCode::LamportOverflow)?;
+    **from.try_borrow_mut_lamports()? = from_balance;
+    **to.try_borrow_mut_lamports()? = to_balance;
+    Ok(())
 }
@@ -0,0 +1,118 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use common::*;
+use heres_program::{activity_attestation_message, heartbeat_message};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn capsule_with_oracle(oracle: &Keypair) -> (ProgramTestContext, Keypair) {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+    process(&mut ctx, &[instructions::set_activity_oracle(&admin, &oracle.pubkey())], &[]).await.unwrap();
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    (ctx, owner)
+}
+
+#[tokio::test]
+async fn oracle_attestation_refreshes_activity_without_owner_signature() {
+    let oracle = Keypair::new();
+    let (mut ctx, owner) = capsule_with_oracle(&oracle).await;
+    warp_forward(&mut ctx, 50).await;
+
+    let activity_at = now(&mut ctx).await - 5;
+    let message = activity_attestation_message(&pda::capsule(&owner.pubkey()).0, activity_at);
+    let attest = [ed25519_ix(&oracle, &message), instructions::attest_activity(&owner.pubkey(), activity_at)];
+    process(&mut ctx, &attest, &[]).await.unwrap();
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.last_activity, activity_at);
+
+    // Replaying the same attestation cannot move the timer again
+    assert!(process(&mut ctx, &attest, &[]).await.is_err());
+
+    // Activity was 45s ago, so the 60s inactivity period is not met yet
+    warp_forward(&mut ctx, 20).await;
+    assert!(process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.is_err());
+}
+
+#[tokio::test]
+async fn attestation_from_unregistered_signer_is_rejected() {
+    let oracle = Keypair::new();
+    let (mut ctx, owner) = capsule_with_oracle(&oracle).await;
+    warp_forward(&mut ctx, 10).await;
+
+    let activity_at = now(&mut ctx).await;
+    let message = activity_attestation_message(&pda::capsule(&owner.pubkey()).0, activity_at);
+    let forged = [
+        ed25519_ix(&Keypair::new(), &message),
+        instructions::attest_activity(&owner.pubkey(), activity_at),
+    ];
+    assert!(process(&mut ctx, &forged, &[]).await.is_err());
+
+    // A valid signature over a different timestamp does not cover this one
+    let other = activity_attestation_message(&pda::capsule(&owner.pubkey()).0, activity_at - 1);
+    let mismatched = [ed25519_ix(&oracle, &other), instructions::attest_activity(&owner.pubkey(), activity_at)];
+    assert!(process(&mut ctx, &mismatched, &[]).await.is_err());
+
+    // Without the Ed25519 instruction at all
+    assert!(process(&mut ctx, &[instructions::attest_activity(&owner.pubkey(), activity_at)], &[])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn future_attestation_is_rejected() {
+    let oracle = Keypair::new();
+    let (mut ctx, owner) = capsule_with_oracle(&oracle).await;
+
+    let activity_at = now(&mut ctx).await + 3_600;
+    let message = activity_attestation_message(&pda::capsule(&owner.pubkey()).0, activity_at);
+    let attest = [ed25519_ix(&oracle, &message), instructions::attest_activity(&owner.pubkey(), activity_at)];
+    assert!(process(&mut ctx, &attest, &[]).await.is_err());
+}
+
+#[tokio::test]
+async fn relayed_owner_heartbeat_refreshes_activity_once_per_nonce() {
+    let (mut ctx, owner) = capsule_with_oracle(&Keypair::new()).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+    warp_forward(&mut ctx, 50).await;
+
+    // Relayed by the test payer; the owner only signs the message
+    let heartbeat = |nonce| {
+        [
+            ed25519_ix(&owner, &heartbeat_message(&capsule_key, nonce)),
+            instructions::update_activity_with_signature(&owner.pubkey(), nonce),
+        ]
+    };
+    process(&mut ctx, &heartbeat(1), &[]).await.unwrap();
+    let state = capsule(&mut ctx, &owner.pubkey()).await;
+    assert_eq!(state.activity_nonce, 1);
+    assert_eq!(state.last_activity, now(&mut ctx).await);
+
+    // Replays and older nonces are rejected
+    warp_forward(&mut ctx, 5).await;
+    assert!(process(&mut ctx, &heartbeat(1), &[]).await.is_err());
+    process(&mut ctx, &heartbeat(5), &[]).await.unwrap();
+    assert!(process(&mut ctx, &heartbeat(3), &[]).await.is_err());
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.activity_nonce, 5);
+
+    // Signed by someone other than the owner
+    let forged = [
+        ed25519_ix(&Keypair::new(), &heartbeat_message(&capsule_key, 6)),
+        instructions::update_activity_with_signature(&owner.pubkey(), 6),
+    ];
+    assert!(process(&mut ctx, &forged, &[]).await.is_err());
+}
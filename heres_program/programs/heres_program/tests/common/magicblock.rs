@@ -0,0 +1,109 @@
+//! Stand-ins for the MagicBlock programs the capsule talks to (Magic program, Permission program and the
+//! delegation program), so instructions that reference or CPI into them run on a local bank.
+//!
+//! Every instruction the stubs receive is recorded, so tests can assert what was delegated, scheduled or
+//! cancelled. Records live in one process-wide log shared by the tests running in parallel; look them up
+//! by an account unique to the test (`cpis_with`). The delegation stub also does what the real program
+//! does to the delegated account on L1: it keeps the account (now owned by the delegation program) and
+//! restores its data from the buffer. Everything else is accepted and changes nothing.
+
+use std::sync::Mutex;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::AnchorDeserialize;
+use ephemeral_rollups_sdk::consts::MAGIC_PROGRAM_ID;
+use ephemeral_rollups_sdk::types::DelegateAccountArgs;
+use heres_client::pda::delegation_program_id;
+use heres_program::PERMISSION_PROGRAM_ID;
+use magicblock_magic_program_api::instruction::MagicBlockInstruction;
+use solana_program_test::{processor, ProgramTest};
+
+/// Discriminator of the delegation program's `delegate` instruction
+const DELEGATE_DISCRIMINATOR: [u8; 8] = [0; 8];
+
+static RECORDED: Mutex<Vec<RecordedCpi>> = Mutex::new(Vec::new());
+
+/// An instruction received by one of the stubs
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedCpi {
+    pub program_id: Pubkey,
+    pub accounts: Vec<AccountMeta>,
+    pub data: Vec<u8>,
+}
+
+impl RecordedCpi {
+    pub fn keys(&self) -> Vec<Pubkey> {
+        self.accounts.iter().map(|meta| meta.pubkey).collect()
+    }
+
+    /// Decodes a Magic program instruction (ScheduleTask, CancelTask, ScheduleCommit...)
+    pub fn magic_instruction(&self) -> MagicBlockInstruction {
+        assert_eq!(self.program_id, MAGIC_PROGRAM_ID, "not a Magic program instruction");
+        bincode::deserialize(&self.data).unwrap()
+    }
+
+    /// Decodes the arguments of a delegation program `delegate` instruction
+    pub fn delegate_args(&self) -> DelegateAccountArgs {
+        assert_eq!(self.program_id, delegation_program_id(), "not a delegation program instruction");
+        assert!(self.data.starts_with(&DELEGATE_DISCRIMINATOR), "not a delegate instruction");
+        DelegateAccountArgs::try_from_slice(&self.data[DELEGATE_DISCRIMINATOR.len()..]).unwrap()
+    }
+}
+
+/// Registers the stubs as native builtins; leaves `prefer_bpf` off, so set it afterwards.
+pub fn add_magicblock_stubs(pt: &mut ProgramTest) {
+    // There is no SBF build of the stubs, so they must not be looked up as shared objects
+    pt.prefer_bpf(false);
+    pt.add_program("magic_program_stub", MAGIC_PROGRAM_ID, processor!(magic_program));
+    pt.add_program("permission_program_stub", PERMISSION_PROGRAM_ID, processor!(permission_program));
+    pt.add_program("delegation_program_stub", delegation_program_id(), processor!(delegation_program));
+}
+
+/// Instructions received by `program_id` that passed `account`, in the order they were made
+pub fn cpis_with(program_id: &Pubkey, account: &Pubkey) -> Vec<RecordedCpi> {
+    RECORDED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|cpi| cpi.program_id == *program_id && cpi.accounts.iter().any(|meta| meta.pubkey == *account))
+        .cloned()
+        .collect()
+}
+
+fn record(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) {
+    let accounts = accounts
+        .iter()
+        .map(|info| AccountMeta {
+            pubkey: *info.key,
+            is_signer: info.is_signer,
+            is_writable: info.is_writable,
+        })
+        .collect();
+    RECORDED.lock().unwrap().push(RecordedCpi {
+        program_id: *program_id,
+        accounts,
+        data: data.to_vec(),
+    });
+}
+
+fn magic_program(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    record(program_id, accounts, data);
+    Ok(())
+}
+
+fn permission_program(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    record(program_id, accounts, data);
+    Ok(())
+}
+
+/// Accounts of `delegate`: payer, delegated account, owner program, buffer, delegation record,
+/// delegation metadata, system program
+fn delegation_program(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    record(program_id, accounts, data);
+    if data.starts_with(&DELEGATE_DISCRIMINATOR) {
+        let (delegated, buffer) = (&accounts[1], &accounts[3]);
+        delegated.try_borrow_mut_data()?.copy_from_slice(&buffer.try_borrow_data()?);
+    }
+    Ok(())
+}
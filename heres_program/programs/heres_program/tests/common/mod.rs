@@ -0,0 +1,268 @@
+//! Shared harness for the SVM integration tests.
+//!
+//! The program is loaded from its SBF build (`cargo test-sbf` builds it and points
+//! `SBF_OUT_DIR` at `target/deploy`), so every test here runs fully offline.
+//!
+//! Instructions and PDAs come from `heres-client`, the same builders the CLI uses.
+#![allow(dead_code)]
+
+mod magicblock;
+
+pub use heres_client::{instructions, pda, LAMPORTS_PER_SOL};
+pub use magicblock::*;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{instruction::Instruction, program_pack::Pack, system_program};
+use anchor_lang::Event;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::spl_token;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use heres_program::{FeeConfig, IntentCapsule, IntentData, ProgramConfig};
+use solana_ed25519_program::new_ed25519_instruction_with_signature;
+use solana_program_test::{BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+/// Bank with the SBF build of the program and stubbed MagicBlock programs
+pub fn program_test() -> ProgramTest {
+    let mut pt = ProgramTest::new("heres_program", heres_program::ID, None);
+    add_magicblock_stubs(&mut pt);
+    pt.prefer_bpf(true);
+    pt
+}
+
+/// Adds a system-owned wallet funded with `lamports`.
+pub fn add_wallet(pt: &mut ProgramTest, lamports: u64) -> Keypair {
+    let keypair = Keypair::new();
+    pt.add_account(
+        keypair.pubkey(),
+        Account::new(lamports, 0, &system_program::ID),
+    );
+    keypair
+}
+
+/// Adds an SPL mint (9 decimals, no freeze authority) owned by the token program.
+pub fn add_mint(pt: &mut ProgramTest, mint_authority: &Pubkey) -> Pubkey {
+    let mint = Pubkey::new_unique();
+    let mut data = vec![0u8; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        mint_authority: Some(*mint_authority).into(),
+        supply: u64::MAX / 2,
+        decimals: 9,
+        is_initialized: true,
+        freeze_authority: None.into(),
+    }
+    .pack_into_slice(&mut data);
+    pt.add_account(
+        mint,
+        Account {
+            lamports: LAMPORTS_PER_SOL,
+            data,
+            owner: spl_token::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    mint
+}
+
+/// Adds `owner`'s associated token account for `mint` holding `amount`.
+pub fn add_token_account(pt: &mut ProgramTest, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let address = get_associated_token_address(owner, mint);
+    let mut data = vec![0u8; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        delegate: None.into(),
+        state: spl_token::state::AccountState::Initialized,
+        is_native: None.into(),
+        delegated_amount: 0,
+        close_authority: None.into(),
+    }
+    .pack_into_slice(&mut data);
+    pt.add_account(
+        address,
+        Account {
+            lamports: LAMPORTS_PER_SOL,
+            data,
+            owner: spl_token::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    address
+}
+
+pub async fn process(
+    ctx: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // A fresh blockhash keeps retried (otherwise identical) transactions from being deduplicated
+    let blockhash = ctx.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers: Vec<&Keypair> = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&ctx.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    ctx.banks_client.process_transaction(tx).await
+}
+
+/// Like `process`, but returns the transaction's log messages.
+pub async fn process_with_logs(
+    ctx: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> std::result::Result<Vec<String>, BanksClientError> {
+    let blockhash = ctx.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers: Vec<&Keypair> = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&ctx.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    let outcome = ctx.banks_client.process_transaction_with_metadata(tx).await?;
+    outcome.result?;
+    Ok(outcome.metadata.map(|m| m.log_messages).unwrap_or_default())
+}
+
+/// Asserts that a single-instruction transaction failed with the Anchor error `code` (a program `ErrorCode`
+/// or a constraint error).
+pub fn assert_error(result: std::result::Result<(), BanksClientError>, code: impl Into<u32>) {
+    let code = code.into();
+    match result {
+        Err(err) => assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(code)),
+            "expected error {code}"
+        ),
+        Ok(()) => panic!("expected error {code}, transaction succeeded"),
+    }
+}
+
+/// `instruction` with `owner`'s signer account swapped for `impostor`, keeping every derived address.
+pub fn signed_by(mut instruction: Instruction, owner: &Pubkey, impostor: &Pubkey) -> Instruction {
+    for meta in instruction.accounts.iter_mut().filter(|m| m.is_signer && m.pubkey == *owner) {
+        meta.pubkey = *impostor;
+    }
+    instruction
+}
+
+/// Anchor events of type `E` in `logs`, in emission order.
+pub fn events<E: Event>(logs: &[String]) -> Vec<E> {
+    logs.iter()
+        .filter_map(|line| line.strip_prefix("Program data: "))
+        .filter_map(|data| BASE64_STANDARD.decode(data).ok())
+        .filter(|data| data.starts_with(E::DISCRIMINATOR))
+        .map(|data| E::try_from_slice(&data[E::DISCRIMINATOR.len()..]).unwrap())
+        .collect()
+}
+
+pub async fn lamports(ctx: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    ctx.banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .map(|a| a.lamports)
+        .unwrap_or(0)
+}
+
+pub async fn token_balance(ctx: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    let account = ctx.banks_client.get_account(*address).await.unwrap().unwrap();
+    spl_token::state::Account::unpack(&account.data).unwrap().amount
+}
+
+/// Rent-exempt minimum for an account holding `data_len` bytes.
+pub async fn rent_minimum(ctx: &mut ProgramTestContext, data_len: usize) -> u64 {
+    let rent: Rent = ctx.banks_client.get_sysvar().await.unwrap();
+    rent.minimum_balance(data_len)
+}
+
+pub async fn fetch<T: AccountDeserialize>(ctx: &mut ProgramTestContext, address: &Pubkey) -> T {
+    let account = ctx.banks_client.get_account(*address).await.unwrap().unwrap();
+    T::try_deserialize(&mut account.data.as_slice()).unwrap()
+}
+
+pub async fn fee_config(ctx: &mut ProgramTestContext) -> FeeConfig {
+    fetch(ctx, &pda::fee_config().0).await
+}
+
+pub async fn program_config(ctx: &mut ProgramTestContext) -> ProgramConfig {
+    fetch(ctx, &pda::program_config().0).await
+}
+
+pub async fn capsule(ctx: &mut ProgramTestContext, owner: &Pubkey) -> IntentCapsule {
+    fetch(ctx, &pda::capsule(owner).0).await
+}
+
+pub async fn intent_data(ctx: &mut ProgramTestContext, owner: &Pubkey) -> IntentData {
+    fetch(ctx, &pda::intent_data(&pda::capsule(owner).0).0).await
+}
+
+/// Moves the bank clock forward by `seconds`.
+pub async fn warp_forward(ctx: &mut ProgramTestContext, seconds: i64) {
+    let mut clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    ctx.set_sysvar(&clock);
+}
+
+pub async fn now(ctx: &mut ProgramTestContext) -> i64 {
+    let clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp
+}
+
+/// Ed25519 program instruction carrying `signer`'s signature over `message`.
+pub fn ed25519_ix(signer: &Keypair, message: &[u8]) -> Instruction {
+    let signature: [u8; 64] = signer.sign_message(message).into();
+    new_ed25519_instruction_with_signature(message, &signature, &signer.pubkey().to_bytes())
+}
+
+/// Builds an intent JSON with `totalAmount` (in SOL) and `(address, amount, amountType)` beneficiaries.
+pub fn intent_json(total_sol: &str, beneficiaries: &[(Pubkey, &str, &str)]) -> Vec<u8> {
+    let beneficiaries: Vec<String> = beneficiaries
+        .iter()
+        .map(|(address, amount, amount_type)| {
+            format!(
+                r#"{{"address":"{}","amount":"{}","amountType":"{}"}}"#,
+                address, amount, amount_type
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"intent":"test","beneficiaries":[{}],"totalAmount":"{}","inactivityDays":1,"delayDays":0}}"#,
+        beneficiaries.join(","),
+        total_sol
+    )
+    .into_bytes()
+}
+
+/// Initializes both the fee config and the program config with `authority` as admin.
+pub async fn init_configs(
+    ctx: &mut ProgramTestContext,
+    fee_recipient: &Pubkey,
+    creation_fee_lamports: u64,
+    execution_fee_bps: u16,
+) {
+    let authority = ctx.payer.pubkey();
+    process(
+        ctx,
+        &[
+            instructions::init_fee_config(&authority, fee_recipient, creation_fee_lamports, execution_fee_bps),
+            instructions::init_program_config(&authority),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "test-sbf")]
+//! Compute units of the instructions whose cost grows with the intent, measured at 1, 5, 10 and 25
+//! beneficiaries and checked against the baselines recorded in `tests/fixtures/compute_units_*.json`.
+//! Going more than MARGIN_PERCENT over a baseline fails the test. Run with `RECORD_COMPUTE_UNITS=1` to
+//! rewrite the baselines (after an optimization, or a deliberate cost increase) and commit the result;
+//! `-- --nocapture` prints the measurements.
+
+mod common;
+
+use anchor_lang::solana_program::instruction::Instruction;
+use common::*;
+use heres_program::MAX_BENEFICIARIES;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::{collections::BTreeMap, fs, path::Path};
+
+const BENEFICIARY_COUNTS: [usize; 4] = [1, 5, 10, MAX_BENEFICIARIES];
+/// Highest limit a transaction can request; measurements run under it so they are not cut off
+const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+const EXECUTION_FEE_BPS: u16 = 300;
+/// How far over its recorded baseline an instruction may go
+const MARGIN_PERCENT: u64 = 15;
+
+/// Compute units by instruction, then by beneficiary count
+type Measurements = BTreeMap<String, BTreeMap<usize, u64>>;
+
+/// Compute units the program itself consumed running `instruction` (CPIs it makes included)
+async fn measure(ctx: &mut ProgramTestContext, instruction: Instruction, signers: &[&Keypair]) -> u64 {
+    let logs = process_with_logs(
+        ctx,
+        &[ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS), instruction],
+        signers,
+    )
+    .await
+    .unwrap();
+    let prefix = format!("Program {} consumed ", heres_program::ID);
+    logs.iter()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|units| units.parse().ok())
+        .expect("program did not log its compute units")
+}
+
+/// Checks `measured` against the baseline in `tests/fixtures/{file}`, or rewrites it when RECORD_COMPUTE_UNITS is set
+fn check_baseline(file: &str, measured: &Measurements) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(file);
+    if std::env::var_os("RECORD_COMPUTE_UNITS").is_some() {
+        fs::write(&path, serde_json::to_string_pretty(measured).unwrap() + "\n").unwrap();
+        return;
+    }
+    let recorded = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no baseline at {}; record one with RECORD_COMPUTE_UNITS=1", path.display()));
+    let baseline: Measurements = serde_json::from_str(&recorded).unwrap();
+    for (name, counts) in measured {
+        for (&beneficiaries, &consumed) in counts {
+            let recorded = *baseline
+                .get(name)
+                .and_then(|counts| counts.get(&beneficiaries))
+                .unwrap_or_else(|| panic!("{file} has no baseline for {name} with {beneficiaries} beneficiaries"));
+            let allowed = recorded + recorded * MARGIN_PERCENT / 100;
+            eprintln!("{name:<16} {beneficiaries:>2} beneficiaries: {consumed:>9} CU (baseline {recorded})");
+            assert!(
+                consumed <= allowed,
+                "{name} with {beneficiaries} beneficiaries used {consumed} CU, over its allowance of {allowed}"
+            );
+        }
+    }
+}
+
+/// Runs create → update → execute → distribute for `heirs`, recording what each step consumed.
+/// `mint` switches to an SPL capsule with fixed amounts; SOL capsules use percentages.
+async fn run(
+    ctx: &mut ProgramTestContext,
+    owner: &Keypair,
+    fee_recipient: &Pubkey,
+    mint: Option<&Pubkey>,
+    heirs: &[Pubkey],
+    measured: &mut Measurements,
+) {
+    let n = heirs.len();
+    let share = LAMPORTS_PER_SOL / n as u64;
+    let (amount, amount_type) = match mint {
+        Some(_) => (format!("{}.{:09}", share / LAMPORTS_PER_SOL, share % LAMPORTS_PER_SOL), "fixed"),
+        None => ((100 / n).to_string(), "percentage"),
+    };
+    let beneficiaries: Vec<_> = heirs.iter().map(|h| (*h, amount.as_str(), amount_type)).collect();
+    let intent = intent_json("1", &beneficiaries);
+    let reversed: Vec<_> = beneficiaries.iter().rev().copied().collect();
+    let payer = ctx.payer.pubkey();
+
+    init_configs(ctx, fee_recipient, 0, EXECUTION_FEE_BPS).await;
+    process(ctx, &[instructions::set_keeper_bounty(&payer, 0, 50)], &[]).await.unwrap();
+
+    let mut record = |name: &str, consumed: u64| {
+        measured.entry(name.to_string()).or_default().insert(n, consumed);
+    };
+
+    let create = instructions::create_capsule(&owner.pubkey(), None, mint, 60, intent);
+    record("create_capsule", measure(ctx, create, &[owner]).await);
+    let update = instructions::update_intent(&owner.pubkey(), intent_json("1", &reversed));
+    record("update_intent", measure(ctx, update, &[owner]).await);
+
+    warp_forward(ctx, 61).await;
+    let execute = instructions::execute_intent(&owner.pubkey(), None);
+    record("execute_intent", measure(ctx, execute, &[]).await);
+
+    let distribute = instructions::distribute_assets(&owner.pubkey(), &payer, fee_recipient, mint, heirs, None);
+    record("distribute_assets", measure(ctx, distribute, &[]).await);
+    assert!(capsule(ctx, &owner.pubkey()).await.distributed_at.is_some());
+}
+
+#[tokio::test]
+async fn sol_capsule_stays_within_compute_budgets() {
+    let mut measured = Measurements::new();
+    for n in BENEFICIARY_COUNTS {
+        let mut pt = program_test();
+        let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+        let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+        let heirs: Vec<_> = (0..n).map(|_| add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey()).collect();
+        let mut ctx = pt.start_with_context().await;
+        run(&mut ctx, &owner, &fee_recipient, None, &heirs, &mut measured).await;
+    }
+    check_baseline("compute_units_sol.json", &measured);
+}
+
+#[tokio::test]
+async fn spl_capsule_stays_within_compute_budgets() {
+    let mut measured = Measurements::new();
+    for n in BENEFICIARY_COUNTS {
+        let mut pt = program_test();
+        let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+        let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+        let mint = add_mint(&mut pt, &owner.pubkey());
+        add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+        let heirs: Vec<_> = (0..n).map(|_| add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey()).collect();
+        for heir in &heirs {
+            add_token_account(&mut pt, &mint, heir, 0);
+        }
+        let mut ctx = pt.start_with_context().await;
+        run(&mut ctx, &owner, &fee_recipient, Some(&mint), &heirs, &mut measured).await;
+    }
+    check_baseline("compute_units_spl.json", &measured);
+}
@@ -0,0 +1,64 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use ephemeral_rollups_sdk::consts::{MAGIC_CONTEXT_ID, MAGIC_PROGRAM_ID};
+use heres_program::{
+    commit_and_undelegate_crank_ix, crank_task_id, execute_intent_crank_ix, instruction, ID,
+    PERMISSION_PROGRAM_ID,
+};
+
+#[test]
+fn crank_task_id_is_deterministic_per_capsule_and_nonce() {
+    let capsule = Pubkey::new_unique();
+    assert_eq!(crank_task_id(&capsule, 0), crank_task_id(&capsule, 0));
+    assert_ne!(crank_task_id(&capsule, 0), crank_task_id(&capsule, 1));
+    assert_ne!(crank_task_id(&capsule, 0), crank_task_id(&Pubkey::new_unique(), 0));
+}
+
+#[test]
+fn scheduled_instruction_decodes_to_execute_intent() {
+    let owner = Pubkey::new_unique();
+    let (capsule, _) = Pubkey::find_program_address(&[b"intent_capsule", owner.as_ref()], &ID);
+    let (vault, vault_bump) = Pubkey::find_program_address(&[b"capsule_vault", owner.as_ref()], &ID);
+    let (permission, _) =
+        Pubkey::find_program_address(&[b"permission", capsule.as_ref()], &PERMISSION_PROGRAM_ID);
+    let (program_config, _) = Pubkey::find_program_address(&[b"program_config"], &ID);
+
+    let ix = execute_intent_crank_ix(&capsule, &owner, vault_bump).unwrap();
+    assert_eq!(ix.program_id, ID);
+
+    let (discriminator, args) = ix.data.split_at(instruction::ExecuteIntent::DISCRIMINATOR.len());
+    assert_eq!(discriminator, instruction::ExecuteIntent::DISCRIMINATOR);
+    instruction::ExecuteIntent::try_from_slice(args).unwrap();
+
+    let expected = [
+        (capsule, true),
+        (vault, false),
+        (PERMISSION_PROGRAM_ID, false),
+        (permission, false),
+        (program_config, false),
+        // Optional keeper left out: the crank has no signer
+        (ID, false),
+    ];
+    assert_eq!(ix.accounts.len(), expected.len());
+    for (meta, (pubkey, is_writable)) in ix.accounts.iter().zip(expected) {
+        assert_eq!(meta.pubkey, pubkey);
+        assert_eq!(meta.is_writable, is_writable);
+        assert!(!meta.is_signer);
+    }
+}
+
+#[test]
+fn crank_commits_and_undelegates_capsule_and_vault() {
+    let owner = Pubkey::new_unique();
+    let (capsule, _) = Pubkey::find_program_address(&[b"intent_capsule", owner.as_ref()], &ID);
+    let (vault, vault_bump) = Pubkey::find_program_address(&[b"capsule_vault", owner.as_ref()], &ID);
+
+    let ix = commit_and_undelegate_crank_ix(&capsule, &owner, vault_bump).unwrap();
+    assert_eq!(&ix.data[..], instruction::CommitAndUndelegateCapsule::DISCRIMINATOR);
+    let writable: Vec<_> = ix.accounts.iter().filter(|m| m.is_writable).map(|m| m.pubkey).collect();
+    assert!(writable.contains(&capsule));
+    assert!(writable.contains(&vault));
+    assert!(writable.contains(&MAGIC_CONTEXT_ID));
+    assert!(ix.accounts.iter().any(|m| m.pubkey == MAGIC_PROGRAM_ID));
+    assert!(ix.accounts.iter().all(|m| !m.is_signer));
+}
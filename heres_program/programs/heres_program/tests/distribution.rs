@@ -0,0 +1,259 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use common::*;
+use heres_program::CapsuleVault;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+
+const VAULT_SPACE: usize = 8 + CapsuleVault::LEN;
+
+#[tokio::test]
+async fn update_cannot_raise_total_above_locked_amount() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir_a, "50", "percentage"), (heir_b, "50", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    // totalAmount would claim 5 SOL while only 1 SOL was locked
+    let oversized = intent_json("5", &[(heir_a, "50", "percentage"), (heir_b, "50", "percentage")]);
+    assert!(process(&mut ctx, &[instructions::update_intent(&owner.pubkey(), oversized)], &[&owner])
+        .await
+        .is_err());
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    process(
+        &mut ctx,
+        &[instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir_a, heir_b], None)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(lamports(&mut ctx, &heir_a).await, LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 2);
+    assert_eq!(lamports(&mut ctx, &heir_b).await, LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 2);
+    let vault_rent = rent_minimum(&mut ctx, VAULT_SPACE).await;
+    assert_eq!(lamports(&mut ctx, &pda::vault(&owner.pubkey()).0).await, vault_rent);
+}
+
+#[tokio::test]
+async fn fixed_amounts_above_total_are_rejected_on_create() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 500).await;
+
+    let oversized = intent_json("1", &[(heir_a, "3", "fixed"), (heir_b, "3", "fixed")]);
+    assert!(process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, oversized)],
+        &[&owner],
+    )
+    .await
+    .is_err());
+
+    let intent = intent_json("1", &[(heir_a, "0.5", "fixed"), (heir_b, "0.5", "fixed")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    process(
+        &mut ctx,
+        &[instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir_a, heir_b], None)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let fee = LAMPORTS_PER_SOL * 500 / 10_000;
+    let paid_out = lamports(&mut ctx, &heir_a).await + lamports(&mut ctx, &heir_b).await
+        - 2 * LAMPORTS_PER_SOL;
+    assert_eq!(paid_out, LAMPORTS_PER_SOL - fee);
+    let vault_rent = rent_minimum(&mut ctx, VAULT_SPACE).await;
+    assert_eq!(lamports(&mut ctx, &pda::vault(&owner.pubkey()).0).await, vault_rent);
+}
+
+#[tokio::test]
+async fn owner_sets_rent_recipient() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let rent_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.designated_rent_recipient(), None);
+
+    process(
+        &mut ctx,
+        &[instructions::set_rent_recipient(&owner.pubkey(), &rent_recipient)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        capsule(&mut ctx, &owner.pubkey()).await.designated_rent_recipient(),
+        Some(rent_recipient)
+    );
+}
+
+/// Creates, executes and distributes a 1 SOL capsule paying `heirs` equal shares.
+async fn distributed_capsule(
+    ctx: &mut ProgramTestContext,
+    owner: &Keypair,
+    fee_recipient: &Pubkey,
+    heirs: &[Pubkey],
+    rent_recipient: Option<&Pubkey>,
+) {
+    let share = format!("{}", 100 / heirs.len());
+    let beneficiaries: Vec<_> = heirs.iter().map(|h| (*h, share.as_str(), "percentage")).collect();
+    let mut instructions = vec![instructions::create_capsule(
+        &owner.pubkey(),
+        None,
+        None,
+        60,
+        intent_json("1", &beneficiaries),
+    )];
+    if let Some(recipient) = rent_recipient {
+        instructions.push(instructions::set_rent_recipient(&owner.pubkey(), recipient));
+    }
+    process(ctx, &instructions, &[owner]).await.unwrap();
+    warp_forward(ctx, 61).await;
+    let payer = ctx.payer.pubkey();
+    process(
+        ctx,
+        &[
+            instructions::execute_intent(&owner.pubkey(), None),
+            instructions::distribute_assets(&owner.pubkey(), &payer, fee_recipient, None, heirs, None),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn close_refunds_rent_to_last_beneficiary_by_default() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+    distributed_capsule(&mut ctx, &owner, &fee_recipient, &[heir_a, heir_b], None).await;
+
+    let (capsule_key, vault_key) = (pda::capsule(&owner.pubkey()).0, pda::vault(&owner.pubkey()).0);
+    let intent_key = pda::intent_data(&capsule_key).0;
+    let capsule_rent = lamports(&mut ctx, &capsule_key).await;
+    let vault_rent = lamports(&mut ctx, &vault_key).await;
+    let intent_rent = lamports(&mut ctx, &intent_key).await;
+    let heir_b_before = lamports(&mut ctx, &heir_b).await;
+
+    assert!(process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir_a, None)], &[])
+        .await
+        .is_err());
+    process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir_b, None)], &[]).await.unwrap();
+
+    assert_eq!(
+        lamports(&mut ctx, &heir_b).await,
+        heir_b_before + capsule_rent + vault_rent + intent_rent
+    );
+    for closed in [capsule_key, intent_key, vault_key] {
+        assert!(ctx.banks_client.get_account(closed).await.unwrap().is_none());
+    }
+}
+
+#[tokio::test]
+async fn close_refunds_rent_to_designated_recipient() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let rent_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+    distributed_capsule(&mut ctx, &owner, &fee_recipient, &[heir], Some(&rent_recipient)).await;
+
+    let (capsule_key, vault_key) = (pda::capsule(&owner.pubkey()).0, pda::vault(&owner.pubkey()).0);
+    let intent_key = pda::intent_data(&capsule_key).0;
+    let capsule_rent = lamports(&mut ctx, &capsule_key).await;
+    let vault_rent = lamports(&mut ctx, &vault_key).await;
+    let intent_rent = lamports(&mut ctx, &intent_key).await;
+
+    assert!(process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir, None)], &[])
+        .await
+        .is_err());
+    process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &rent_recipient, None)], &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        lamports(&mut ctx, &rent_recipient).await,
+        LAMPORTS_PER_SOL + capsule_rent + vault_rent + intent_rent
+    );
+}
+
+#[tokio::test]
+async fn capsule_cannot_be_closed_before_or_distributed_after_distribution() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    assert!(process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir, None)], &[])
+        .await
+        .is_err());
+
+    let distribute = instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir], None);
+    process(&mut ctx, std::slice::from_ref(&distribute), &[]).await.unwrap();
+    assert!(capsule(&mut ctx, &owner.pubkey()).await.distributed_at.is_some());
+    assert!(process(&mut ctx, &[distribute], &[]).await.is_err());
+}
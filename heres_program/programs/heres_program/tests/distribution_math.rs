@@ -0,0 +1,125 @@
+use anchor_lang::prelude::Pubkey;
+use heres_program::{
+    compute_distribution, execution_fee, IntentBeneficiary, IntentShare, ParsedIntent, MAX_BENEFICIARIES,
+};
+use proptest::prelude::*;
+
+fn intent(total_amount: u64, shares: Vec<IntentShare>) -> ParsedIntent {
+    ParsedIntent {
+        total_amount,
+        beneficiaries: shares
+            .into_iter()
+            .map(|share| IntentBeneficiary {
+                address: Pubkey::new_unique(),
+                share,
+            })
+            .collect(),
+    }
+}
+
+/// Any share the JSON could decode to, including percentages validate_intent would reject
+fn share() -> impl Strategy<Value = IntentShare> {
+    prop_oneof![
+        any::<u64>().prop_map(IntentShare::Fixed),
+        (0.0f64..=100.0).prop_map(IntentShare::Percentage),
+        any::<f64>().prop_map(IntentShare::Percentage),
+    ]
+}
+
+fn shares() -> impl Strategy<Value = Vec<IntentShare>> {
+    prop::collection::vec(share(), 1..=MAX_BENEFICIARIES)
+}
+
+/// Fixed amounts that together fit in the intent total, as validate_intent requires: (total, amounts)
+fn validated_fixed() -> impl Strategy<Value = (u64, Vec<u64>)> {
+    (1..=MAX_BENEFICIARIES, 1..=u64::MAX / MAX_BENEFICIARIES as u64)
+        .prop_flat_map(|(n, cap)| (prop::collection::vec(0..=cap, n), 0..=cap))
+        .prop_map(|(amounts, slack)| (amounts.iter().sum::<u64>() + slack, amounts))
+        .prop_filter("intent total must be positive", |(total, _)| *total > 0)
+}
+
+proptest! {
+    #[test]
+    fn payouts_and_fee_add_up_to_the_vault(total: u64, fee_bps: u16, intent_total: u64, shares in shares()) {
+        let payouts = compute_distribution(total, fee_bps, &intent(intent_total, shares.clone()));
+        prop_assert_eq!(payouts.len(), shares.len());
+        let paid: u128 = payouts.iter().map(|&p| p as u128).sum();
+        prop_assert_eq!(paid + execution_fee(total, fee_bps) as u128, total as u128);
+    }
+
+    #[test]
+    fn fee_is_bounded_by_its_rate(total: u64, fee_bps in 0u16..=10_000) {
+        let fee = execution_fee(total, fee_bps) as u128;
+        prop_assert!(fee * 10_000 <= total as u128 * fee_bps as u128);
+        prop_assert!((fee + 1) * 10_000 > total as u128 * fee_bps as u128);
+    }
+
+    #[test]
+    fn same_inputs_give_the_same_payouts(total: u64, fee_bps: u16, intent_total: u64, shares in shares()) {
+        let first = compute_distribution(total, fee_bps, &intent(intent_total, shares.clone()));
+        // Addresses play no part in the math
+        let second = compute_distribution(total, fee_bps, &intent(intent_total, shares));
+        prop_assert_eq!(first, second);
+    }
+
+    #[test]
+    fn nobody_gets_more_than_their_proportional_share(
+        total: u64,
+        fee_bps in 0u16..=10_000,
+        (intent_total, amounts) in validated_fixed(),
+    ) {
+        let shares = amounts.iter().copied().map(IntentShare::Fixed).collect();
+        let payouts = compute_distribution(total, fee_bps, &intent(intent_total, shares));
+        let remaining = (total - execution_fee(total, fee_bps)) as u128;
+        let (last, others) = payouts.split_last().unwrap();
+        for (payout, amount) in others.iter().zip(&amounts) {
+            // Rounded down by less than one base unit
+            prop_assert_eq!(*payout as u128, *amount as u128 * remaining / intent_total as u128);
+        }
+        // The rounding remainder (and any unallocated slack) goes to the last beneficiary
+        let last_amount = *amounts.last().unwrap() as u128;
+        prop_assert!(*last as u128 >= last_amount * remaining / intent_total as u128);
+    }
+
+    #[test]
+    fn equal_amounts_are_paid_equally(total: u64, fee_bps in 0u16..=10_000, n in 1..=MAX_BENEFICIARIES, amount in 1..=u64::MAX / MAX_BENEFICIARIES as u64) {
+        let shares = vec![IntentShare::Fixed(amount); n];
+        let payouts = compute_distribution(total, fee_bps, &intent(amount * n as u64, shares));
+        let (last, others) = payouts.split_last().unwrap();
+        let share = (total - execution_fee(total, fee_bps)) / n as u64;
+        prop_assert!(others.iter().all(|&p| p == share));
+        prop_assert!(*last >= share && *last - share < n as u64);
+    }
+}
+
+#[test]
+fn extreme_inputs_do_not_overflow() {
+    let huge = intent(
+        u64::MAX,
+        vec![
+            IntentShare::Fixed(u64::MAX),
+            IntentShare::Percentage(f64::INFINITY),
+            IntentShare::Fixed(1),
+        ],
+    );
+    let payouts = compute_distribution(u64::MAX, 10_000, &huge);
+    assert_eq!(payouts, vec![0, 0, 0]);
+
+    let payouts = compute_distribution(u64::MAX, 0, &huge);
+    assert_eq!(payouts, vec![u64::MAX, 0, 0]);
+
+    // A vault far beyond u64::MAX / 10_000 (a large SPL balance) still pays its fee
+    assert_eq!(execution_fee(u64::MAX, 300), (u64::MAX as u128 * 300 / 10_000) as u64);
+    // A fee rate above 100% is treated as 100%
+    assert_eq!(execution_fee(1_000, u16::MAX), 1_000);
+}
+
+#[test]
+fn zero_intent_total_pays_the_vault_to_the_last_beneficiary() {
+    let payouts = compute_distribution(
+        1_000,
+        300,
+        &intent(0, vec![IntentShare::Fixed(5), IntentShare::Fixed(5)]),
+    );
+    assert_eq!(payouts, vec![0, 970]);
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use common::*;
+use heres_program::{
+    ActivitySource, ActivityUpdated, AssetsDistributed, BeneficiaryPaid, CapsuleCreated, FeeCharged, FeeKind,
+    IntentUpdated, RentRecipientUpdated,
+};
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+
+#[tokio::test]
+async fn lifecycle_emits_events_with_capsule_and_owner() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 500).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+
+    let intent = intent_json("1", &[(heir_a, "60", "percentage"), (heir_b, "40", "percentage")]);
+    let logs = process_with_logs(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent.clone())],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let created: Vec<CapsuleCreated> = events(&logs);
+    assert_eq!(created.len(), 1);
+    assert_eq!((created[0].capsule, created[0].owner), (capsule_key, owner.pubkey()));
+    assert_eq!((created[0].total_amount, created[0].inactivity_period), (LAMPORTS_PER_SOL, 60));
+
+    let logs = process_with_logs(&mut ctx, &[instructions::update_intent(&owner.pubkey(), intent.clone())], &[&owner])
+        .await
+        .unwrap();
+    let updated: Vec<IntentUpdated> = events(&logs);
+    assert_eq!(updated[0].intent_len as usize, intent.len());
+
+    let check_in = instructions::update_activity(&owner.pubkey(), false);
+    let logs = process_with_logs(&mut ctx, &[check_in], &[&owner]).await.unwrap();
+    let activity: Vec<ActivityUpdated> = events(&logs);
+    assert_eq!(activity[0].source, ActivitySource::Owner);
+    assert_eq!(activity[0].last_activity, now(&mut ctx).await);
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    let logs = process_with_logs(
+        &mut ctx,
+        &[instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir_a, heir_b], None)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let fees: Vec<FeeCharged> = events(&logs);
+    assert_eq!(fees.len(), 1);
+    assert_eq!((fees[0].kind, fees[0].recipient), (FeeKind::Execution, fee_recipient));
+    assert_eq!(fees[0].amount, LAMPORTS_PER_SOL * 500 / 10_000);
+
+    let paid: Vec<BeneficiaryPaid> = events(&logs);
+    let payouts: Vec<_> = paid.iter().map(|p| (p.index, p.beneficiary)).collect();
+    assert_eq!(payouts, vec![(0, heir_a), (1, heir_b)]);
+    let after_fee = LAMPORTS_PER_SOL - fees[0].amount;
+    assert_eq!(paid.iter().map(|p| p.amount).sum::<u64>(), after_fee);
+
+    let distributed: Vec<AssetsDistributed> = events(&logs);
+    assert_eq!((distributed[0].capsule, distributed[0].amount), (capsule_key, after_fee));
+}
+
+#[tokio::test]
+async fn set_rent_recipient_emits_the_new_recipient() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &Pubkey::new_unique(), 0, 0).await;
+    let intent = intent_json("1", &[(Pubkey::new_unique(), "100", "percentage")]);
+    process(&mut ctx, &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)], &[&owner])
+        .await
+        .unwrap();
+
+    let rent_recipient = Pubkey::new_unique();
+    let logs = process_with_logs(
+        &mut ctx,
+        &[instructions::set_rent_recipient(&owner.pubkey(), &rent_recipient)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let updated: Vec<RentRecipientUpdated> = events(&logs);
+    assert_eq!(updated.len(), 1);
+    assert_eq!((updated[0].capsule, updated[0].owner), (pda::capsule(&owner.pubkey()).0, owner.pubkey()));
+    assert_eq!(updated[0].rent_recipient, rent_recipient);
+}
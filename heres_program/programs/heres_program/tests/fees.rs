@@ -0,0 +1,582 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use anchor_lang::{AnchorSerialize, Discriminator};
+use anchor_spl::associated_token::get_associated_token_address;
+use common::*;
+use heres_program::{ErrorCode, FeeConfig, FeeConfigMigrated, FeeConfigV0};
+use solana_sdk::{
+    account::Account,
+    rent::Rent,
+    signature::{Keypair, Signer},
+};
+
+const CREATION_FEE: u64 = 10_000_000;
+const EXECUTION_FEE_BPS: u16 = 300;
+
+#[tokio::test]
+async fn sol_capsule_pays_creation_and_execution_fee_to_recipient_wallet() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+
+    let intent = intent_json("1", &[(heir_a, "60", "percentage"), (heir_b, "40", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + CREATION_FEE);
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    process(
+        &mut ctx,
+        &[instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir_a, heir_b], None)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let execution_fee = LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    let remaining = LAMPORTS_PER_SOL - execution_fee;
+    assert_eq!(
+        lamports(&mut ctx, &fee_recipient).await,
+        LAMPORTS_PER_SOL + CREATION_FEE + execution_fee
+    );
+    let heir_a_share = 600_000_000 * remaining / LAMPORTS_PER_SOL;
+    assert_eq!(lamports(&mut ctx, &heir_a).await, LAMPORTS_PER_SOL + heir_a_share);
+    assert_eq!(lamports(&mut ctx, &heir_b).await, LAMPORTS_PER_SOL + remaining - heir_a_share);
+}
+
+#[tokio::test]
+async fn spl_execution_fee_goes_to_recipient_ata_created_on_demand() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mint = add_mint(&mut pt, &owner.pubkey());
+    add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+    let heir_ata = add_token_account(&mut pt, &mint, &heir, 0);
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+
+    let intent = intent_json("2", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), Some(&mint), 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let vault_ata = get_associated_token_address(&pda::vault(&owner.pubkey()).0, &mint);
+    assert_eq!(token_balance(&mut ctx, &vault_ata).await, 2 * LAMPORTS_PER_SOL);
+
+    let fee_ata = get_associated_token_address(&fee_recipient, &mint);
+    assert!(ctx.banks_client.get_account(fee_ata).await.unwrap().is_none());
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    process(
+        &mut ctx,
+        &[instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, Some(&mint), &[heir], None)],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let execution_fee = 2 * LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    assert_eq!(token_balance(&mut ctx, &fee_ata).await, execution_fee);
+    assert_eq!(token_balance(&mut ctx, &heir_ata).await, 2 * LAMPORTS_PER_SOL - execution_fee);
+    assert_eq!(token_balance(&mut ctx, &vault_ata).await, 0);
+    // The fee wallet itself only ever holds SOL
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + CREATION_FEE);
+}
+
+#[tokio::test]
+async fn spl_distribution_rejects_fee_recipient_mismatch() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let impostor = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mint = add_mint(&mut pt, &owner.pubkey());
+    add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+    add_token_account(&mut pt, &mint, &heir, 0);
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+
+    init_configs(&mut ctx, &fee_recipient, 0, EXECUTION_FEE_BPS).await;
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, Some(&mint), 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    let result = process(
+        &mut ctx,
+        &[instructions::distribute_assets(&owner.pubkey(), &payer, &impostor, Some(&mint), &[heir], None)],
+        &[],
+    )
+    .await;
+    assert!(result.is_err());
+    let impostor_ata = get_associated_token_address(&impostor, &mint);
+    assert!(ctx.banks_client.get_account(impostor_ata).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn keeper_bounties_are_carved_out_of_the_execution_fee() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let executor = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let distributor = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+
+    init_configs(&mut ctx, &fee_recipient, 0, EXECUTION_FEE_BPS).await;
+    process(&mut ctx, &[instructions::set_keeper_bounty(&admin, 0, 50)], &[]).await.unwrap();
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(
+        &mut ctx,
+        &[instructions::execute_intent(&owner.pubkey(), Some(&executor.pubkey()))],
+        &[&executor],
+    )
+    .await
+    .unwrap();
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.executed_by, executor.pubkey());
+
+    process(
+        &mut ctx,
+        &[instructions::distribute_assets(
+            &owner.pubkey(),
+            &distributor.pubkey(),
+            &fee_recipient,
+            None,
+            &[heir],
+            Some(&executor.pubkey()),
+        )],
+        &[&distributor],
+    )
+    .await
+    .unwrap();
+
+    let execution_fee = LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    let bounty = LAMPORTS_PER_SOL * 50 / 10_000;
+    assert_eq!(lamports(&mut ctx, &executor.pubkey()).await, LAMPORTS_PER_SOL + bounty);
+    assert_eq!(lamports(&mut ctx, &distributor.pubkey()).await, LAMPORTS_PER_SOL + bounty);
+    assert_eq!(
+        lamports(&mut ctx, &fee_recipient).await,
+        LAMPORTS_PER_SOL + execution_fee - 2 * bounty
+    );
+    assert_eq!(lamports(&mut ctx, &heir).await, 2 * LAMPORTS_PER_SOL - execution_fee);
+}
+
+#[tokio::test]
+async fn sol_executor_bounty_below_rent_exemption_goes_to_the_platform() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    // The executor drained its wallet after executing, so a small credit can't make it rent exempt
+    let executor = Keypair::new();
+    let distributor = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+
+    init_configs(&mut ctx, &fee_recipient, 0, EXECUTION_FEE_BPS).await;
+    let bounty = 1_000;
+    process(&mut ctx, &[instructions::set_keeper_bounty(&admin, bounty, 0)], &[]).await.unwrap();
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(
+        &mut ctx,
+        &[instructions::execute_intent(&owner.pubkey(), Some(&executor.pubkey()))],
+        &[&executor],
+    )
+    .await
+    .unwrap();
+
+    process(
+        &mut ctx,
+        &[instructions::distribute_assets(
+            &owner.pubkey(),
+            &distributor.pubkey(),
+            &fee_recipient,
+            None,
+            &[heir],
+            Some(&executor.pubkey()),
+        )],
+        &[&distributor],
+    )
+    .await
+    .unwrap();
+
+    let execution_fee = LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    assert!(capsule(&mut ctx, &owner.pubkey()).await.distributed_at.is_some());
+    assert_eq!(lamports(&mut ctx, &executor.pubkey()).await, 0);
+    assert_eq!(lamports(&mut ctx, &distributor.pubkey()).await, LAMPORTS_PER_SOL + bounty);
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + execution_fee - bounty);
+    assert_eq!(lamports(&mut ctx, &heir).await, 2 * LAMPORTS_PER_SOL - execution_fee);
+}
+
+#[tokio::test]
+async fn keeper_bounty_is_capped_at_the_execution_fee_and_requires_the_real_executor() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let executor = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let impostor = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+
+    init_configs(&mut ctx, &fee_recipient, 0, EXECUTION_FEE_BPS).await;
+    // A fixed bounty larger than the whole execution fee
+    process(&mut ctx, &[instructions::set_keeper_bounty(&admin, LAMPORTS_PER_SOL, 0)], &[]).await.unwrap();
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(
+        &mut ctx,
+        &[instructions::execute_intent(&owner.pubkey(), Some(&executor.pubkey()))],
+        &[&executor],
+    )
+    .await
+    .unwrap();
+
+    let payer = ctx.payer.pubkey();
+    let to_impostor = instructions::distribute_assets(
+        &owner.pubkey(),
+        &payer,
+        &fee_recipient,
+        None,
+        &[heir],
+        Some(&impostor),
+    );
+    assert!(process(&mut ctx, &[to_impostor], &[]).await.is_err());
+
+    let distribute = instructions::distribute_assets(
+        &owner.pubkey(),
+        &payer,
+        &fee_recipient,
+        None,
+        &[heir],
+        Some(&executor.pubkey()),
+    );
+    process(&mut ctx, &[distribute], &[]).await.unwrap();
+
+    // The executor takes the whole execution fee; nothing is left for the distributor or platform
+    let execution_fee = LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    assert_eq!(lamports(&mut ctx, &executor.pubkey()).await, LAMPORTS_PER_SOL + execution_fee);
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL);
+    assert_eq!(lamports(&mut ctx, &heir).await, 2 * LAMPORTS_PER_SOL - execution_fee);
+}
+
+#[tokio::test]
+async fn keeper_bounty_above_100_percent_is_rejected() {
+    let mut pt = program_test();
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, EXECUTION_FEE_BPS).await;
+
+    assert!(process(&mut ctx, &[instructions::set_keeper_bounty(&admin, 0, 10_001)], &[]).await.is_err());
+}
+
+#[tokio::test]
+async fn fee_authority_transfer_takes_a_proposal_and_an_acceptance() {
+    let mut pt = program_test();
+    let successor = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let stranger = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+
+    // Nothing to accept before a proposal, and only the authority proposes
+    assert_error(
+        process(&mut ctx, &[instructions::accept_fee_authority(&successor.pubkey())], &[&successor]).await,
+        ErrorCode::Unauthorized,
+    );
+    let by_stranger = instructions::propose_fee_authority(&stranger.pubkey(), &stranger.pubkey());
+    assert_error(process(&mut ctx, &[by_stranger], &[&stranger]).await, ErrorCode::Unauthorized);
+
+    process(&mut ctx, &[instructions::propose_fee_authority(&admin, &successor.pubkey())], &[])
+        .await
+        .unwrap();
+    let proposed = fee_config(&mut ctx).await;
+    assert_eq!(proposed.authority, admin);
+    assert_eq!(proposed.pending_authority, Some(successor.pubkey()));
+
+    // Only the proposed key can accept
+    assert_error(
+        process(&mut ctx, &[instructions::accept_fee_authority(&stranger.pubkey())], &[&stranger]).await,
+        ErrorCode::Unauthorized,
+    );
+    process(&mut ctx, &[instructions::accept_fee_authority(&successor.pubkey())], &[&successor])
+        .await
+        .unwrap();
+    let transferred = fee_config(&mut ctx).await;
+    assert_eq!(transferred.authority, successor.pubkey());
+    assert_eq!(transferred.pending_authority, None);
+
+    // The previous authority is locked out, the new one is in
+    assert_error(
+        process(&mut ctx, &[instructions::update_fee_config(&admin, 0, 0)], &[]).await,
+        ErrorCode::Unauthorized,
+    );
+    process(&mut ctx, &[instructions::update_fee_config(&successor.pubkey(), 0, 0)], &[&successor])
+        .await
+        .unwrap();
+    assert_eq!(fee_config(&mut ctx).await.creation_fee_lamports, 0);
+}
+
+#[tokio::test]
+async fn only_the_fee_authority_rotates_the_fee_recipient() {
+    let mut pt = program_test();
+    let stranger = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let new_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+
+    let by_stranger = instructions::set_fee_recipient(&stranger.pubkey(), &stranger.pubkey());
+    assert_error(process(&mut ctx, &[by_stranger], &[&stranger]).await, ErrorCode::Unauthorized);
+    assert_eq!(fee_config(&mut ctx).await.fee_recipient, fee_recipient);
+
+    process(&mut ctx, &[instructions::set_fee_recipient(&admin, &new_recipient)], &[])
+        .await
+        .unwrap();
+    assert_eq!(fee_config(&mut ctx).await.fee_recipient, new_recipient);
+
+    // Creation fees now go to the new wallet and the old one is rejected
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    let to_old = instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), None, 60, intent.clone());
+    assert_error(process(&mut ctx, &[to_old], &[&owner]).await, ErrorCode::InvalidFeeConfig);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), Some(&new_recipient), None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut ctx, &new_recipient).await, LAMPORTS_PER_SOL + CREATION_FEE);
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn lengthening_the_fee_delay_applies_at_once_and_shortening_waits_for_it() {
+    let mut pt = program_test();
+    let stranger = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+
+    let by_stranger = instructions::set_fee_update_delay(&stranger.pubkey(), 0);
+    assert_error(process(&mut ctx, &[by_stranger], &[&stranger]).await, ErrorCode::Unauthorized);
+    assert_error(
+        process(&mut ctx, &[instructions::set_fee_update_delay(&admin, -1)], &[]).await,
+        ErrorCode::InvalidFeeConfig,
+    );
+
+    process(&mut ctx, &[instructions::set_fee_update_delay(&admin, 100)], &[]).await.unwrap();
+    let lengthened = fee_config(&mut ctx).await;
+    assert_eq!(lengthened.fee_update_delay, 100);
+    assert_eq!(lengthened.pending_delay_effective_at, None);
+
+    // Shortening is held back by the current delay
+    process(&mut ctx, &[instructions::set_fee_update_delay(&admin, 10)], &[]).await.unwrap();
+    let queued = fee_config(&mut ctx).await;
+    assert_eq!(queued.fee_update_delay, 100);
+    assert_eq!(queued.pending_fee_update_delay, 10);
+    assert_eq!(queued.pending_delay_effective_at, Some(now(&mut ctx).await + 100));
+
+    warp_forward(&mut ctx, 50).await;
+    assert_error(
+        process(&mut ctx, &[instructions::apply_fee_update()], &[]).await,
+        ErrorCode::FeeUpdateNotReady,
+    );
+    warp_forward(&mut ctx, 50).await;
+    process(&mut ctx, &[instructions::apply_fee_update()], &[]).await.unwrap();
+    let shortened = fee_config(&mut ctx).await;
+    assert_eq!(shortened.fee_update_delay, 10);
+    assert_eq!(shortened.pending_delay_effective_at, None);
+}
+
+#[tokio::test]
+async fn fee_increases_wait_for_the_delay_and_a_decrease_cancels_them() {
+    let mut pt = program_test();
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+    process(&mut ctx, &[instructions::set_fee_update_delay(&admin, 100)], &[]).await.unwrap();
+
+    assert_error(
+        process(&mut ctx, &[instructions::apply_fee_update()], &[]).await,
+        ErrorCode::NoPendingFeeUpdate,
+    );
+
+    // An increase is queued, not applied
+    process(&mut ctx, &[instructions::update_fee_config(&admin, 2 * CREATION_FEE, 500)], &[])
+        .await
+        .unwrap();
+    let queued = fee_config(&mut ctx).await;
+    assert_eq!((queued.creation_fee_lamports, queued.execution_fee_bps), (CREATION_FEE, EXECUTION_FEE_BPS));
+    assert_eq!((queued.pending_creation_fee_lamports, queued.pending_execution_fee_bps), (2 * CREATION_FEE, 500));
+    assert_eq!(queued.pending_fees_effective_at, Some(now(&mut ctx).await + 100));
+
+    warp_forward(&mut ctx, 99).await;
+    assert_error(
+        process(&mut ctx, &[instructions::apply_fee_update()], &[]).await,
+        ErrorCode::FeeUpdateNotReady,
+    );
+    warp_forward(&mut ctx, 1).await;
+    process(&mut ctx, &[instructions::apply_fee_update()], &[]).await.unwrap();
+    let applied = fee_config(&mut ctx).await;
+    assert_eq!((applied.creation_fee_lamports, applied.execution_fee_bps), (2 * CREATION_FEE, 500));
+    assert_eq!(applied.pending_fees_effective_at, None);
+
+    // A decrease applies at once and drops a queued increase
+    process(&mut ctx, &[instructions::update_fee_config(&admin, 3 * CREATION_FEE, 500)], &[])
+        .await
+        .unwrap();
+    assert!(fee_config(&mut ctx).await.pending_fees_effective_at.is_some());
+    process(&mut ctx, &[instructions::update_fee_config(&admin, CREATION_FEE, 100)], &[])
+        .await
+        .unwrap();
+    let decreased = fee_config(&mut ctx).await;
+    assert_eq!((decreased.creation_fee_lamports, decreased.execution_fee_bps), (CREATION_FEE, 100));
+    assert_eq!(decreased.pending_fees_effective_at, None);
+    warp_forward(&mut ctx, 100).await;
+    assert_error(
+        process(&mut ctx, &[instructions::apply_fee_update()], &[]).await,
+        ErrorCode::NoPendingFeeUpdate,
+    );
+}
+
+#[tokio::test]
+async fn fee_config_from_the_first_deployment_is_migrated_in_place() {
+    let mut pt = program_test();
+    let authority = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let stranger = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+
+    // The 74-byte account init_fee_config wrote before the timelock, authority transfer and bounty fields
+    let mut data = FeeConfig::DISCRIMINATOR.to_vec();
+    FeeConfigV0 {
+        authority: authority.pubkey(),
+        fee_recipient,
+        creation_fee_lamports: CREATION_FEE,
+        execution_fee_bps: EXECUTION_FEE_BPS,
+    }
+    .serialize(&mut data)
+    .unwrap();
+    assert_eq!(data.len(), 8 + 74);
+    pt.add_account(
+        pda::fee_config().0,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: heres_program::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    let mut ctx = pt.start_with_context().await;
+
+    // The old layout cannot be loaded by the current program
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    let create = instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), None, 60, intent);
+    assert!(process(&mut ctx, std::slice::from_ref(&create), &[&owner]).await.is_err());
+
+    let by_stranger = instructions::migrate_fee_config(&stranger.pubkey());
+    assert_error(process(&mut ctx, &[by_stranger], &[&stranger]).await, ErrorCode::Unauthorized);
+
+    let logs = process_with_logs(&mut ctx, &[instructions::migrate_fee_config(&authority.pubkey())], &[&authority])
+        .await
+        .unwrap();
+    let migrated_events: Vec<FeeConfigMigrated> = events(&logs);
+    assert_eq!(migrated_events.len(), 1);
+    assert_eq!(
+        (migrated_events[0].authority, migrated_events[0].fee_recipient),
+        (authority.pubkey(), fee_recipient)
+    );
+    assert_eq!(
+        (migrated_events[0].creation_fee_lamports, migrated_events[0].execution_fee_bps),
+        (CREATION_FEE, EXECUTION_FEE_BPS)
+    );
+    let account = ctx.banks_client.get_account(pda::fee_config().0).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), 8 + FeeConfig::LEN);
+    assert_eq!(account.lamports, rent_minimum(&mut ctx, 8 + FeeConfig::LEN).await);
+    let migrated = fee_config(&mut ctx).await;
+    assert_eq!(migrated.authority, authority.pubkey());
+    assert_eq!(migrated.fee_recipient, fee_recipient);
+    assert_eq!(migrated.creation_fee_lamports, CREATION_FEE);
+    assert_eq!(migrated.execution_fee_bps, EXECUTION_FEE_BPS);
+    assert_eq!(migrated.pending_authority, None);
+    assert_eq!(migrated.fee_update_delay, 0);
+    assert_eq!(migrated.pending_fees_effective_at, None);
+    assert_eq!(migrated.pending_delay_effective_at, None);
+    assert_eq!((migrated.keeper_bounty_lamports, migrated.keeper_bounty_bps), (0, 0));
+
+    assert_error(
+        process(&mut ctx, &[instructions::migrate_fee_config(&authority.pubkey())], &[&authority]).await,
+        ErrorCode::FeeConfigAlreadyMigrated,
+    );
+
+    // Capsules pay the creation fee again
+    process(&mut ctx, &[instructions::init_program_config(&authority.pubkey())], &[&authority])
+        .await
+        .unwrap();
+    process(&mut ctx, &[create], &[&owner]).await.unwrap();
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + CREATION_FEE);
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use common::*;
+use heres_program::{IntentData, MAX_BENEFICIARIES};
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+
+#[tokio::test]
+async fn intent_account_grows_and_shrinks_with_the_intent() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let heirs: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let small = intent_json("1", &[(heirs[0], "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, small.clone())],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let intent_key = pda::intent_data(&pda::capsule(&owner.pubkey()).0).0;
+    assert_eq!(intent_data(&mut ctx, &owner.pubkey()).await.data, small);
+    let small_rent = lamports(&mut ctx, &intent_key).await;
+    assert_eq!(small_rent, rent_minimum(&mut ctx, IntentData::space(small.len())).await);
+
+    // Past the old 1 KB inline limit
+    let memo = "m".repeat(900);
+    let large: Vec<u8> = String::from_utf8(intent_json(
+        "1",
+        &[(heirs[0], "40", "percentage"), (heirs[1], "30", "percentage"), (heirs[2], "30", "percentage")],
+    ))
+    .unwrap()
+    .replacen(r#""intent":"test""#, &format!(r#""intent":"{}""#, memo), 1)
+    .into_bytes();
+    assert!(large.len() > 1024);
+    let owner_before = lamports(&mut ctx, &owner.pubkey()).await;
+    process(&mut ctx, &[instructions::update_intent(&owner.pubkey(), large.clone())], &[&owner]).await.unwrap();
+    let large_rent = rent_minimum(&mut ctx, IntentData::space(large.len())).await;
+    assert_eq!(intent_data(&mut ctx, &owner.pubkey()).await.data, large);
+    assert_eq!(lamports(&mut ctx, &intent_key).await, large_rent);
+    assert_eq!(lamports(&mut ctx, &owner.pubkey()).await, owner_before - (large_rent - small_rent));
+
+    // Shrinking refunds the difference to the owner
+    process(&mut ctx, &[instructions::update_intent(&owner.pubkey(), small.clone())], &[&owner]).await.unwrap();
+    assert_eq!(intent_data(&mut ctx, &owner.pubkey()).await.data, small);
+    assert_eq!(lamports(&mut ctx, &intent_key).await, small_rent);
+    assert_eq!(lamports(&mut ctx, &owner.pubkey()).await, owner_before);
+}
+
+#[tokio::test]
+async fn intents_over_the_beneficiary_limit_are_rejected() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let heirs: Vec<Pubkey> = (0..=MAX_BENEFICIARIES).map(|_| Pubkey::new_unique()).collect();
+    let entries = |count: usize| -> Vec<(Pubkey, &str, &str)> {
+        heirs[..count].iter().map(|h| (*h, "0.01", "fixed")).collect()
+    };
+
+    let too_many = intent_json("1", &entries(MAX_BENEFICIARIES + 1));
+    assert!(process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, too_many.clone())],
+        &[&owner],
+    )
+    .await
+    .is_err());
+
+    let at_limit = intent_json("1", &entries(MAX_BENEFICIARIES));
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, at_limit)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert!(process(&mut ctx, &[instructions::update_intent(&owner.pubkey(), too_many)], &[&owner])
+        .await
+        .is_err());
+}
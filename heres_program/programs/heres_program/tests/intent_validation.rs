@@ -0,0 +1,105 @@
+use anchor_lang::prelude::*;
+use heres_program::{parse_intent, validate_intent, ErrorCode, IntentShare, MAX_BENEFICIARIES};
+
+const SOL: u64 = 1_000_000_000;
+
+fn intent(total: &str, beneficiaries: &[(String, &str, &str)]) -> Vec<u8> {
+    let beneficiaries: Vec<String> = beneficiaries
+        .iter()
+        .map(|(address, amount, amount_type)| {
+            format!(r#"{{"address":"{}","amount":"{}","amountType":"{}"}}"#, address, amount, amount_type)
+        })
+        .collect();
+    format!(r#"{{"intent":"test","beneficiaries":[{}],"totalAmount":"{}"}}"#, beneficiaries.join(","), total)
+        .into_bytes()
+}
+
+fn heir() -> String {
+    Pubkey::new_unique().to_string()
+}
+
+fn rejected(intent_data: &[u8], code: ErrorCode) {
+    assert_eq!(validate_intent(intent_data).unwrap_err(), code.into());
+}
+
+#[test]
+fn valid_intents_return_their_total() {
+    let percentages = intent(
+        "2",
+        &[(heir(), "33.33", "percentage"), (heir(), "33.33", "percentage"), (heir(), "33.34", "percentage")],
+    );
+    assert_eq!(validate_intent(&percentages).unwrap(), 2 * SOL);
+
+    let mixed = intent("1", &[(heir(), "0.25", "fixed"), (heir(), "75", "percentage")]);
+    assert_eq!(validate_intent(&mixed).unwrap(), SOL);
+
+    // Fixed amounts may leave a remainder; it goes to the last beneficiary
+    let under = intent("1", &[(heir(), "0.1", "fixed"), (heir(), "0.2", "fixed")]);
+    assert_eq!(validate_intent(&under).unwrap(), SOL);
+}
+
+#[test]
+fn total_amount_must_be_present_and_positive() {
+    rejected(br#"{"beneficiaries":[]}"#, ErrorCode::MissingTotalAmount);
+    rejected(&intent("0", &[(heir(), "100", "percentage")]), ErrorCode::InvalidTotalAmount);
+    rejected(&intent("-1", &[(heir(), "100", "percentage")]), ErrorCode::InvalidTotalAmount);
+    rejected(&intent("lots", &[(heir(), "100", "percentage")]), ErrorCode::InvalidTotalAmount);
+    rejected(b"not json", ErrorCode::InvalidIntentJson);
+    rejected(&[0xff, 0xfe], ErrorCode::InvalidIntentEncoding);
+}
+
+#[test]
+fn beneficiaries_must_be_distinct_valid_and_within_limits() {
+    rejected(&intent("1", &[]), ErrorCode::NoBeneficiaries);
+    rejected(&intent("1", &[("not-a-key".to_string(), "100", "percentage")]), ErrorCode::InvalidBeneficiaryAddress);
+
+    let twice = heir();
+    rejected(
+        &intent("1", &[(twice.clone(), "50", "percentage"), (twice, "50", "percentage")]),
+        ErrorCode::DuplicateBeneficiary,
+    );
+
+    let many: Vec<(String, &str, &str)> = (0..=MAX_BENEFICIARIES).map(|_| (heir(), "0.01", "fixed")).collect();
+    rejected(&intent("1", &many), ErrorCode::TooManyBeneficiaries);
+}
+
+#[test]
+fn shares_must_be_well_formed_and_fit_the_total() {
+    rejected(&intent("1", &[(heir(), "100", "share")]), ErrorCode::InvalidAmountType);
+    rejected(&intent("1", &[(heir(), "0", "percentage")]), ErrorCode::BadPercentage);
+    rejected(&intent("1", &[(heir(), "101", "percentage")]), ErrorCode::BadPercentage);
+    rejected(&intent("1", &[(heir(), "half", "percentage")]), ErrorCode::BadPercentage);
+    rejected(&intent("1", &[(heir(), "0", "fixed")]), ErrorCode::InvalidFixedAmount);
+    rejected(
+        &intent("1", &[(heir(), "60", "percentage"), (heir(), "30", "percentage")]),
+        ErrorCode::PercentagesDoNotSumTo100,
+    );
+    rejected(&intent("1", &[(heir(), "0.6", "fixed"), (heir(), "0.6", "fixed")]), ErrorCode::SharesExceedTotal);
+    rejected(
+        &intent("1", &[(heir(), "0.5", "fixed"), (heir(), "60", "percentage")]),
+        ErrorCode::SharesExceedTotal,
+    );
+}
+
+#[test]
+fn distribution_parser_only_checks_structure() {
+    // Written before validation existed: percentages short of 100 must still decode so the capsule can pay out
+    let a = Pubkey::new_unique();
+    let b = Pubkey::new_unique();
+    let legacy = intent("1.5", &[(a.to_string(), "40", "percentage"), (b.to_string(), "0.5", "fixed")]);
+    let parsed = parse_intent(&legacy).unwrap();
+    assert_eq!(parsed.total_amount, 3 * SOL / 2);
+    assert_eq!(parsed.beneficiaries[0].address, a);
+    assert_eq!(parsed.beneficiaries[0].share, IntentShare::Percentage(40.0));
+    assert_eq!(parsed.beneficiaries[0].amount(parsed.total_amount), 600_000_000);
+    assert_eq!(parsed.beneficiaries[1].share, IntentShare::Fixed(SOL / 2));
+
+    assert_eq!(
+        parse_intent(&intent("1", &[(a.to_string(), "-1", "fixed")])).unwrap_err(),
+        ErrorCode::InvalidFixedAmount.into()
+    );
+    assert_eq!(
+        parse_intent(br#"{"totalAmount":"1"}"#).unwrap_err(),
+        ErrorCode::NoBeneficiaries.into()
+    );
+}
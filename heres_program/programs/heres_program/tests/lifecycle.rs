@@ -0,0 +1,196 @@
+#![cfg(feature = "test-sbf")]
+//! End-to-end capsule lifecycle: create → update → warp → execute → distribute → close, for SOL and SPL
+//! capsules with the creation fee, execution fee and keeper bounties all switched on.
+
+mod common;
+
+use anchor_spl::associated_token::get_associated_token_address;
+use common::*;
+use heres_program::{CapsuleVault, ErrorCode};
+use solana_sdk::signature::Signer;
+
+const CREATION_FEE: u64 = 10_000_000;
+const EXECUTION_FEE_BPS: u16 = 300;
+const KEEPER_BOUNTY: u64 = 1_000_000;
+const PERIOD: i64 = 60;
+
+#[tokio::test]
+async fn sol_capsule_full_lifecycle() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let keeper = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let distributor = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+    process(&mut ctx, &[instructions::set_keeper_bounty(&admin, KEEPER_BOUNTY, 0)], &[]).await.unwrap();
+
+    let intent = intent_json("2", &[(heir_a, "50", "percentage"), (heir_b, "50", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), None, PERIOD, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + CREATION_FEE);
+    let vault_rent = rent_minimum(&mut ctx, 8 + CapsuleVault::LEN).await;
+    assert_eq!(lamports(&mut ctx, &pda::vault(&owner.pubkey()).0).await, vault_rent + 2 * LAMPORTS_PER_SOL);
+
+    let split = intent_json("2", &[(heir_a, "25", "percentage"), (heir_b, "75", "percentage")]);
+    process(&mut ctx, &[instructions::update_intent(&owner.pubkey(), split.clone())], &[&owner]).await.unwrap();
+    assert_eq!(intent_data(&mut ctx, &owner.pubkey()).await.data, split);
+
+    // Halfway through the period, then a check-in restarts it
+    warp_forward(&mut ctx, PERIOD / 2).await;
+    assert_error(
+        process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await,
+        ErrorCode::InactivityPeriodNotMet,
+    );
+    process(&mut ctx, &[instructions::update_activity(&owner.pubkey(), false)], &[&owner]).await.unwrap();
+    warp_forward(&mut ctx, PERIOD / 2 + 10).await;
+    assert_error(
+        process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await,
+        ErrorCode::InactivityPeriodNotMet,
+    );
+
+    warp_forward(&mut ctx, PERIOD / 2).await;
+    let execute = instructions::execute_intent(&owner.pubkey(), Some(&keeper.pubkey()));
+    process(&mut ctx, std::slice::from_ref(&execute), &[&keeper]).await.unwrap();
+    let executed = capsule(&mut ctx, &owner.pubkey()).await;
+    assert!(!executed.is_active);
+    assert_eq!(executed.executed_by, keeper.pubkey());
+    assert_error(process(&mut ctx, &[execute], &[&keeper]).await, ErrorCode::CapsuleInactive);
+
+    let distribute = instructions::distribute_assets(
+        &owner.pubkey(),
+        &distributor.pubkey(),
+        &fee_recipient,
+        None,
+        &[heir_a, heir_b],
+        Some(&keeper.pubkey()),
+    );
+    process(&mut ctx, std::slice::from_ref(&distribute), &[&distributor]).await.unwrap();
+
+    let execution_fee = 2 * LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    let remaining = 2 * LAMPORTS_PER_SOL - execution_fee;
+    let heir_a_share = LAMPORTS_PER_SOL / 2 * remaining / (2 * LAMPORTS_PER_SOL);
+    assert_eq!(lamports(&mut ctx, &keeper.pubkey()).await, LAMPORTS_PER_SOL + KEEPER_BOUNTY);
+    assert_eq!(lamports(&mut ctx, &distributor.pubkey()).await, LAMPORTS_PER_SOL + KEEPER_BOUNTY);
+    assert_eq!(
+        lamports(&mut ctx, &fee_recipient).await,
+        LAMPORTS_PER_SOL + CREATION_FEE + execution_fee - 2 * KEEPER_BOUNTY
+    );
+    assert_eq!(lamports(&mut ctx, &heir_a).await, LAMPORTS_PER_SOL + heir_a_share);
+    assert_eq!(lamports(&mut ctx, &heir_b).await, LAMPORTS_PER_SOL + remaining - heir_a_share);
+    assert_eq!(lamports(&mut ctx, &pda::vault(&owner.pubkey()).0).await, vault_rent);
+
+    // Nobody gets paid twice, whoever signs
+    assert_error(
+        process(&mut ctx, std::slice::from_ref(&distribute), &[&distributor]).await,
+        ErrorCode::AlreadyDistributed,
+    );
+    let heirs = [heir_a, heir_b];
+    let by_admin = instructions::distribute_assets(&owner.pubkey(), &admin, &fee_recipient, None, &heirs, None);
+    assert_error(process(&mut ctx, &[by_admin], &[]).await, ErrorCode::AlreadyDistributed);
+    assert_eq!(lamports(&mut ctx, &heir_b).await, LAMPORTS_PER_SOL + remaining - heir_a_share);
+
+    let heir_b_before = lamports(&mut ctx, &heir_b).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+    let capsule_rent = lamports(&mut ctx, &capsule_key).await;
+    let intent_rent = lamports(&mut ctx, &pda::intent_data(&capsule_key).0).await;
+    process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir_b, None)], &[]).await.unwrap();
+    assert_eq!(
+        lamports(&mut ctx, &heir_b).await,
+        heir_b_before + capsule_rent + vault_rent + intent_rent
+    );
+    assert!(ctx.banks_client.get_account(capsule_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn spl_capsule_full_lifecycle() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let keeper = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mint = add_mint(&mut pt, &owner.pubkey());
+    let owner_ata = add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+    let heir_a_ata = add_token_account(&mut pt, &mint, &heir_a, 0);
+    let heir_b_ata = add_token_account(&mut pt, &mint, &heir_b, 0);
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, EXECUTION_FEE_BPS).await;
+    process(&mut ctx, &[instructions::set_keeper_bounty(&payer, KEEPER_BOUNTY, 0)], &[]).await.unwrap();
+
+    let intent = intent_json("3", &[(heir_a, "1", "fixed"), (heir_b, "2", "fixed")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), Some(&mint), PERIOD, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let vault_ata = get_associated_token_address(&pda::vault(&owner.pubkey()).0, &mint);
+    assert_eq!(token_balance(&mut ctx, &vault_ata).await, 3 * LAMPORTS_PER_SOL);
+    assert_eq!(token_balance(&mut ctx, &owner_ata).await, 2 * LAMPORTS_PER_SOL);
+    // The creation fee is always charged in SOL
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + CREATION_FEE);
+
+    let split = intent_json("3", &[(heir_a, "2", "fixed"), (heir_b, "1", "fixed")]);
+    process(&mut ctx, &[instructions::update_intent(&owner.pubkey(), split)], &[&owner]).await.unwrap();
+
+    warp_forward(&mut ctx, PERIOD - 10).await;
+    assert_error(
+        process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await,
+        ErrorCode::InactivityPeriodNotMet,
+    );
+    warp_forward(&mut ctx, 11).await;
+    process(
+        &mut ctx,
+        &[instructions::execute_intent(&owner.pubkey(), Some(&keeper.pubkey()))],
+        &[&keeper],
+    )
+    .await
+    .unwrap();
+
+    let distribute = instructions::distribute_assets(
+        &owner.pubkey(),
+        &payer,
+        &fee_recipient,
+        Some(&mint),
+        &[heir_a, heir_b],
+        Some(&keeper.pubkey()),
+    );
+    process(&mut ctx, std::slice::from_ref(&distribute), &[]).await.unwrap();
+
+    // Fees and bounties are paid in the capsule's token, to ATAs created on demand
+    let execution_fee = 3 * LAMPORTS_PER_SOL * EXECUTION_FEE_BPS as u64 / 10_000;
+    let remaining = 3 * LAMPORTS_PER_SOL - execution_fee;
+    let heir_a_share = 2 * LAMPORTS_PER_SOL * remaining / (3 * LAMPORTS_PER_SOL);
+    let fee_ata = get_associated_token_address(&fee_recipient, &mint);
+    assert_eq!(token_balance(&mut ctx, &fee_ata).await, execution_fee - 2 * KEEPER_BOUNTY);
+    assert_eq!(
+        token_balance(&mut ctx, &get_associated_token_address(&keeper.pubkey(), &mint)).await,
+        KEEPER_BOUNTY
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &get_associated_token_address(&payer, &mint)).await,
+        KEEPER_BOUNTY
+    );
+    assert_eq!(token_balance(&mut ctx, &heir_a_ata).await, heir_a_share);
+    assert_eq!(token_balance(&mut ctx, &heir_b_ata).await, remaining - heir_a_share);
+    assert_eq!(token_balance(&mut ctx, &vault_ata).await, 0);
+
+    assert_error(process(&mut ctx, &[distribute], &[]).await, ErrorCode::AlreadyDistributed);
+    assert_eq!(token_balance(&mut ctx, &heir_a_ata).await, heir_a_share);
+
+    process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir_b, Some(&mint))], &[])
+        .await
+        .unwrap();
+    assert!(ctx.banks_client.get_account(vault_ata).await.unwrap().is_none());
+}
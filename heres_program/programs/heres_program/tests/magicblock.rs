@@ -0,0 +1,431 @@
+#![cfg(feature = "test-sbf")]
+//! Delegation and crank scheduling against the recording MagicBlock stubs in `common::magicblock`.
+
+mod common;
+
+use anchor_lang::solana_program::{instruction::Instruction, system_program};
+use anchor_lang::{AccountDeserialize, AccountSerialize};
+use common::*;
+use ephemeral_rollups_sdk::consts::MAGIC_PROGRAM_ID;
+use heres_program::{
+    commit_and_undelegate_crank_ix, execute_intent_crank_ix, CapsuleDelegated, CapsuleUndelegated, CrankCancelled,
+    CrankScheduled, DelegateCapsuleArgs, DelegationMigrationCompleted, DelegationMigrationStarted, IntentCapsule,
+    ScheduleExecuteIntentArgs, SetPauseFlagsArgs, PERMISSION_PROGRAM_ID, TEE_VALIDATOR,
+};
+use magicblock_magic_program_api::instruction::MagicBlockInstruction;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::AccountSharedData,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+
+async fn created_capsule(ctx: &mut ProgramTestContext, owner: &Keypair) {
+    let fee_recipient = Pubkey::new_unique();
+    init_configs(ctx, &fee_recipient, 0, 0).await;
+    let intent = intent_json("1", &[(Pubkey::new_unique(), "100", "percentage")]);
+    process(ctx, &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)], &[owner])
+        .await
+        .unwrap();
+}
+
+/// Executes and distributes a capsule paying everything to one heir, returned for close_capsule
+async fn distributed_capsule(ctx: &mut ProgramTestContext, owner: &Keypair) -> Pubkey {
+    let fee_recipient = Pubkey::new_unique();
+    let heir = Pubkey::new_unique();
+    init_configs(ctx, &fee_recipient, 0, 0).await;
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(ctx, &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)], &[owner])
+        .await
+        .unwrap();
+    warp_forward(ctx, 61).await;
+    let payer = ctx.payer.pubkey();
+    process(
+        ctx,
+        &[
+            instructions::execute_intent(&owner.pubkey(), None),
+            instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir], None),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+    heir
+}
+
+/// Rewrites the capsule in place, for state only reachable on the ER or left by earlier program versions
+async fn edit_capsule(ctx: &mut ProgramTestContext, owner: &Pubkey, edit: impl FnOnce(&mut IntentCapsule)) {
+    let capsule_key = pda::capsule(owner).0;
+    let mut account = ctx.banks_client.get_account(capsule_key).await.unwrap().unwrap();
+    let mut capsule = IntentCapsule::try_deserialize(&mut &account.data[..]).unwrap();
+    edit(&mut capsule);
+    capsule.try_serialize(&mut &mut account.data[..]).unwrap();
+    ctx.set_account(&capsule_key, &AccountSharedData::from(account));
+}
+
+fn schedule_crank(owner: &Pubkey, execution_interval_millis: u64, iterations: u64) -> Instruction {
+    instructions::schedule_execute_intent(
+        owner,
+        ScheduleExecuteIntentArgs {
+            execution_interval_millis,
+            iterations,
+        },
+    )
+}
+
+#[tokio::test]
+async fn delegate_capsule_delegates_capsule_and_vault() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    created_capsule(&mut ctx, &owner).await;
+
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+    let vault_key = pda::vault(&owner.pubkey()).0;
+    process(
+        &mut ctx,
+        &[instructions::delegate_capsule(
+            &payer,
+            &owner.pubkey(),
+            DelegateCapsuleArgs {
+                commit_frequency_ms: 30_000,
+                validator: None,
+            },
+        )],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    for (pda, seed) in [(capsule_key, b"intent_capsule".as_slice()), (vault_key, b"capsule_vault".as_slice())] {
+        let delegations = cpis_with(&pda::delegation_program_id(), &pda);
+        assert_eq!(delegations.len(), 1);
+        let delegate = &delegations[0];
+        let args = delegate.delegate_args();
+        assert_eq!(args.commit_frequency_ms, 30_000);
+        assert_eq!(args.validator.map(|v| v.to_bytes()), Some(TEE_VALIDATOR.to_bytes()));
+        assert_eq!(args.seeds, vec![seed.to_vec(), owner.pubkey().to_bytes().to_vec()]);
+        let keys = delegate.keys();
+        assert_eq!(keys[..3], [payer, pda, heres_program::ID]);
+        assert_eq!(keys[6], system_program::ID);
+        assert!(delegate.accounts[1].is_signer && delegate.accounts[1].is_writable);
+
+        let account = ctx.banks_client.get_account(pda).await.unwrap().unwrap();
+        assert_eq!(account.owner, pda::delegation_program_id());
+    }
+
+    // The delegated capsule keeps its data, including the validator recorded before delegating
+    let account = ctx.banks_client.get_account(capsule_key).await.unwrap().unwrap();
+    let capsule = IntentCapsule::try_deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!(capsule.owner, owner.pubkey());
+    assert_eq!(capsule.delegated_validator, TEE_VALIDATOR);
+    assert_eq!(capsule.commit_frequency_ms, 30_000);
+
+    // Delegation goes through the delegation program only
+    assert!(cpis_with(&MAGIC_PROGRAM_ID, &capsule_key).is_empty());
+    assert!(cpis_with(&PERMISSION_PROGRAM_ID, &capsule_key).is_empty());
+}
+
+#[tokio::test]
+async fn schedule_execute_intent_schedules_the_crank_and_cancels_the_previous_task() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+
+    let logs = process_with_logs(&mut ctx, &[schedule_crank(&owner.pubkey(), 60_000, 10)], &[&owner])
+        .await
+        .unwrap();
+    let scheduled = capsule(&mut ctx, &owner.pubkey()).await;
+    let first_task = scheduled.crank_task_id.unwrap();
+    let scheduled_events: Vec<CrankScheduled> = events(&logs);
+    assert_eq!(scheduled_events.len(), 1);
+    assert_eq!((scheduled_events[0].capsule, scheduled_events[0].owner), (capsule_key, owner.pubkey()));
+    assert_eq!((scheduled_events[0].task_id, scheduled_events[0].replaced_task_id), (first_task, None));
+    assert_eq!(
+        (scheduled_events[0].execution_interval_millis, scheduled_events[0].iterations),
+        (60_000, 10)
+    );
+
+    let cpis = cpis_with(&MAGIC_PROGRAM_ID, &capsule_key);
+    assert_eq!(cpis.len(), 1);
+    assert_eq!(cpis[0].keys(), vec![owner.pubkey(), capsule_key]);
+    assert!(cpis[0].accounts[0].is_signer);
+    let MagicBlockInstruction::ScheduleTask(task) = cpis[0].magic_instruction() else {
+        panic!("expected ScheduleTask, got {:?}", cpis[0].magic_instruction());
+    };
+    assert_eq!(task.task_id, first_task);
+    assert_eq!(task.execution_interval_millis, 60_000);
+    assert_eq!(task.iterations, 10);
+    assert_eq!(
+        task.instructions,
+        vec![
+            execute_intent_crank_ix(&capsule_key, &owner.pubkey(), scheduled.vault_bump).unwrap(),
+            commit_and_undelegate_crank_ix(&capsule_key, &owner.pubkey(), scheduled.vault_bump).unwrap(),
+        ]
+    );
+
+    // Rescheduling replaces the running task
+    let logs = process_with_logs(&mut ctx, &[schedule_crank(&owner.pubkey(), 120_000, 5)], &[&owner])
+        .await
+        .unwrap();
+    let second_task = capsule(&mut ctx, &owner.pubkey()).await.crank_task_id.unwrap();
+    assert_ne!(second_task, first_task);
+    let scheduled_events: Vec<CrankScheduled> = events(&logs);
+    assert_eq!(
+        (scheduled_events[0].task_id, scheduled_events[0].replaced_task_id),
+        (second_task, Some(first_task))
+    );
+    let cpis = cpis_with(&MAGIC_PROGRAM_ID, &capsule_key);
+    assert_eq!(cpis.len(), 3);
+    assert_eq!(cpis[1].magic_instruction(), MagicBlockInstruction::CancelTask { task_id: first_task });
+    let MagicBlockInstruction::ScheduleTask(task) = cpis[2].magic_instruction() else {
+        panic!("expected ScheduleTask, got {:?}", cpis[2].magic_instruction());
+    };
+    assert_eq!((task.task_id, task.execution_interval_millis, task.iterations), (second_task, 120_000, 5));
+
+    let logs = process_with_logs(&mut ctx, &[instructions::cancel_scheduled_task(&owner.pubkey())], &[&owner])
+        .await
+        .unwrap();
+    let cancelled: Vec<CrankCancelled> = events(&logs);
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!((cancelled[0].capsule, cancelled[0].owner), (capsule_key, owner.pubkey()));
+    assert_eq!(cancelled[0].task_id, second_task);
+    let cpis = cpis_with(&MAGIC_PROGRAM_ID, &capsule_key);
+    assert_eq!(cpis.len(), 4);
+    assert_eq!(cpis[3].magic_instruction(), MagicBlockInstruction::CancelTask { task_id: second_task });
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.crank_task_id, None);
+}
+
+#[tokio::test]
+async fn only_the_owner_schedules_the_crank() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let stranger = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+
+    let ix = signed_by(schedule_crank(&owner.pubkey(), 60_000, 10), &owner.pubkey(), &stranger.pubkey());
+    assert_error(process(&mut ctx, &[ix], &[&stranger]).await, heres_program::ErrorCode::Unauthorized);
+    assert!(cpis_with(&MAGIC_PROGRAM_ID, &pda::capsule(&owner.pubkey()).0).is_empty());
+}
+
+#[tokio::test]
+async fn migrating_to_the_current_validator_is_rejected() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+
+    // migrate_delegation runs on the ER, where the delegated capsule is still owned by the program;
+    // record the validator on the L1 copy to get the same state here
+    edit_capsule(&mut ctx, &owner.pubkey(), |capsule| capsule.delegated_validator = TEE_VALIDATOR).await;
+
+    assert_error(
+        process(&mut ctx, &[instructions::migrate_delegation(&owner.pubkey(), &TEE_VALIDATOR)], &[&owner]).await,
+        heres_program::ErrorCode::AlreadyDelegatedToValidator,
+    );
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.pending_validator, Pubkey::default());
+    assert!(cpis_with(&MAGIC_PROGRAM_ID, &capsule_key).is_empty());
+}
+
+#[tokio::test]
+async fn execute_intent_clears_the_crank_task() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+    process(&mut ctx, &[schedule_crank(&owner.pubkey(), 60_000, 10)], &[&owner])
+        .await
+        .unwrap();
+    assert!(capsule(&mut ctx, &owner.pubkey()).await.crank_task_id.is_some());
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.crank_task_id, None);
+}
+
+#[tokio::test]
+async fn cancel_capsule_requires_the_crank_task_cancelled() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+    process(&mut ctx, &[schedule_crank(&owner.pubkey(), 60_000, 10)], &[&owner])
+        .await
+        .unwrap();
+
+    let cancel = instructions::cancel_capsule(&owner.pubkey(), None);
+    assert_error(
+        process(&mut ctx, &[cancel.clone()], &[&owner]).await,
+        heres_program::ErrorCode::CrankTaskScheduled,
+    );
+
+    process(&mut ctx, &[instructions::cancel_scheduled_task(&owner.pubkey())], &[&owner]).await.unwrap();
+    process(&mut ctx, &[cancel], &[&owner]).await.unwrap();
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+    assert!(ctx.banks_client.get_account(capsule_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn owner_undelegates_an_active_capsule_while_paused_and_cancels_it() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    created_capsule(&mut ctx, &owner).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+    process(&mut ctx, &[schedule_crank(&owner.pubkey(), 60_000, 10)], &[&owner])
+        .await
+        .unwrap();
+    let task_id = capsule(&mut ctx, &owner.pubkey()).await.crank_task_id.unwrap();
+    edit_capsule(&mut ctx, &owner.pubkey(), |capsule| capsule.delegated_validator = TEE_VALIDATOR).await;
+    let pause_all = SetPauseFlagsArgs {
+        create: true,
+        execute: true,
+        distribute: true,
+    };
+    process(&mut ctx, &[instructions::set_pause_flags(&admin, pause_all)], &[]).await.unwrap();
+
+    let logs = process_with_logs(&mut ctx, &[instructions::undelegate_capsule(&owner.pubkey())], &[&owner])
+        .await
+        .unwrap();
+    let cancelled: Vec<CrankCancelled> = events(&logs);
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].task_id, task_id);
+    let undelegated: Vec<CapsuleUndelegated> = events(&logs);
+    assert_eq!(undelegated.len(), 1);
+    assert_eq!((undelegated[0].capsule, undelegated[0].owner), (capsule_key, owner.pubkey()));
+    assert_eq!(undelegated[0].validator, TEE_VALIDATOR);
+
+    let cpis = cpis_with(&MAGIC_PROGRAM_ID, &capsule_key);
+    assert_eq!(cpis.len(), 3);
+    assert_eq!(cpis[1].magic_instruction(), MagicBlockInstruction::CancelTask { task_id });
+    assert_eq!(cpis[2].magic_instruction(), MagicBlockInstruction::ScheduleCommitAndUndelegate);
+    assert!(cpis[2].keys().contains(&pda::vault(&owner.pubkey()).0));
+    let undelegated_capsule = capsule(&mut ctx, &owner.pubkey()).await;
+    assert!(undelegated_capsule.is_active);
+    assert_eq!(undelegated_capsule.crank_task_id, None);
+
+    // Back on L1 with no crank task, cancel_capsule goes through
+    process(&mut ctx, &[instructions::cancel_capsule(&owner.pubkey(), None)], &[&owner]).await.unwrap();
+    assert!(ctx.banks_client.get_account(capsule_key).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn undelegate_capsule_without_a_crank_task_only_commits() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+
+    let logs = process_with_logs(&mut ctx, &[instructions::undelegate_capsule(&owner.pubkey())], &[&owner])
+        .await
+        .unwrap();
+    assert!(events::<CrankCancelled>(&logs).is_empty());
+    let cpis = cpis_with(&MAGIC_PROGRAM_ID, &capsule_key);
+    assert_eq!(cpis.len(), 1);
+    assert_eq!(cpis[0].magic_instruction(), MagicBlockInstruction::ScheduleCommitAndUndelegate);
+}
+
+#[tokio::test]
+async fn close_capsule_requires_the_crank_task_cancelled() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    let heir = distributed_capsule(&mut ctx, &owner).await;
+
+    // Capsules executed before execute_intent cleared the task can still carry one
+    edit_capsule(&mut ctx, &owner.pubkey(), |capsule| capsule.crank_task_id = Some(7)).await;
+    assert_error(
+        process(&mut ctx, &[instructions::close_capsule(&owner.pubkey(), &heir, None)], &[]).await,
+        heres_program::ErrorCode::CrankTaskScheduled,
+    );
+}
+
+#[tokio::test]
+async fn recreate_capsule_clears_the_crank_task() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    let heir = distributed_capsule(&mut ctx, &owner).await;
+    edit_capsule(&mut ctx, &owner.pubkey(), |capsule| capsule.crank_task_id = Some(7)).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::recreate_capsule(&owner.pubkey(), None, None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.crank_task_id, None);
+}
+
+#[tokio::test]
+async fn update_activity_on_the_er_reschedules_the_crank() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    created_capsule(&mut ctx, &owner).await;
+    process(&mut ctx, &[schedule_crank(&owner.pubkey(), 60_000, 10)], &[&owner])
+        .await
+        .unwrap();
+    let first_task = capsule(&mut ctx, &owner.pubkey()).await.crank_task_id.unwrap();
+
+    let logs = process_with_logs(&mut ctx, &[instructions::update_activity(&owner.pubkey(), true)], &[&owner])
+        .await
+        .unwrap();
+    let rescheduled = capsule(&mut ctx, &owner.pubkey()).await;
+    let scheduled_events: Vec<CrankScheduled> = events(&logs);
+    assert_eq!(scheduled_events.len(), 1);
+    assert_eq!(scheduled_events[0].owner, owner.pubkey());
+    assert_eq!(
+        (scheduled_events[0].task_id, scheduled_events[0].replaced_task_id),
+        (rescheduled.crank_task_id.unwrap(), Some(first_task))
+    );
+    assert_eq!(
+        (scheduled_events[0].execution_interval_millis, scheduled_events[0].iterations),
+        (60_000, 10)
+    );
+}
+
+#[tokio::test]
+async fn migrating_to_another_validator_emits_start_and_completion() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    created_capsule(&mut ctx, &owner).await;
+    let capsule_key = pda::capsule(&owner.pubkey()).0;
+    let other_validator = Pubkey::new_unique();
+    process(
+        &mut ctx,
+        &[instructions::set_allowed_validators(&admin, vec![TEE_VALIDATOR, other_validator])],
+        &[],
+    )
+    .await
+    .unwrap();
+    edit_capsule(&mut ctx, &owner.pubkey(), |capsule| capsule.delegated_validator = TEE_VALIDATOR).await;
+
+    let migrate = instructions::migrate_delegation(&owner.pubkey(), &other_validator);
+    let logs = process_with_logs(&mut ctx, &[migrate], &[&owner]).await.unwrap();
+    let started: Vec<DelegationMigrationStarted> = events(&logs);
+    assert_eq!(started.len(), 1);
+    assert_eq!((started[0].capsule, started[0].owner), (capsule_key, owner.pubkey()));
+    assert_eq!((started[0].from_validator, started[0].to_validator), (TEE_VALIDATOR, other_validator));
+
+    let logs = process_with_logs(&mut ctx, &[instructions::complete_migration(&admin, &owner.pubkey())], &[])
+        .await
+        .unwrap();
+    let completed: Vec<DelegationMigrationCompleted> = events(&logs);
+    assert_eq!(completed.len(), 1);
+    assert_eq!((completed[0].capsule, completed[0].owner), (capsule_key, owner.pubkey()));
+    assert_eq!((completed[0].from_validator, completed[0].to_validator), (TEE_VALIDATOR, other_validator));
+    let delegated: Vec<CapsuleDelegated> = events(&logs);
+    assert_eq!(delegated[0].validator, other_validator);
+}
@@ -0,0 +1,206 @@
+#[cfg(feature = "test-sbf")]
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use heres_program::{IntentCapsule, IntentCapsuleV0};
+
+/// Capsule account bytes in the v0 layout (no version byte, 1 KB intent area), encoded field by field
+/// exactly as the first deployment wrote them, including stale bytes after a shortened intent.
+fn capsule_v0_bytes(owner: &Pubkey, bump: u8, vault_bump: u8, intent: &[u8], executed_at: Option<i64>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + IntentCapsuleV0::LEN);
+    data.extend_from_slice(IntentCapsule::DISCRIMINATOR);
+    data.extend_from_slice(owner.as_ref());
+    data.extend_from_slice(&86_400i64.to_le_bytes()); // inactivity_period
+    data.extend_from_slice(&1_700_000_000i64.to_le_bytes()); // last_activity
+    data.extend_from_slice(&(intent.len() as u32).to_le_bytes());
+    data.extend_from_slice(intent);
+    data.push(executed_at.is_none() as u8); // is_active
+    match executed_at {
+        Some(at) => {
+            data.push(1);
+            data.extend_from_slice(&at.to_le_bytes());
+        }
+        None => data.push(0),
+    }
+    data.push(bump);
+    data.push(vault_bump);
+    data.extend_from_slice(Pubkey::default().as_ref()); // mint
+    data.extend_from_slice(&2u64.to_le_bytes()); // retry_count
+    // Leftovers of a longer intent written before an update_intent
+    data.resize(8 + IntentCapsuleV0::LEN, 0xAB);
+    data
+}
+
+#[test]
+fn v0_bytes_decode_into_current_layout_with_defaults() {
+    let owner = Pubkey::new_unique();
+    let intent = br#"{"intent":"v0","beneficiaries":[],"totalAmount":"1"}"#;
+    let data = capsule_v0_bytes(&owner, 254, 253, intent, None);
+    assert_eq!(data.len(), 8 + IntentCapsuleV0::LEN);
+
+    let v0 = IntentCapsuleV0::deserialize(&mut &data[8..]).unwrap();
+    assert_eq!(v0.owner, owner);
+    assert_eq!(v0.intent_data, intent.to_vec());
+    assert_eq!((v0.bump, v0.vault_bump, v0.retry_count), (254, 253, 2));
+
+    let capsule = IntentCapsule::from(v0);
+    assert_eq!(capsule.version, 0);
+    assert_eq!(capsule.owner, owner);
+    assert_eq!(capsule.inactivity_period, 86_400);
+    assert_eq!(capsule.last_activity, 1_700_000_000);
+    assert!(capsule.is_active && capsule.executed_at.is_none() && capsule.distributed_at.is_none());
+    assert_eq!(capsule.rent_recipient, Pubkey::default());
+    assert_eq!(capsule.crank_task_id, None);
+    assert_eq!(capsule.activity_nonce, 0);
+}
+
+#[test]
+fn executed_v0_capsule_decodes_as_undistributed() {
+    let owner = Pubkey::new_unique();
+    let intent = br#"{"intent":"v0","beneficiaries":[],"totalAmount":"1"}"#;
+    let data = capsule_v0_bytes(&owner, 254, 253, intent, Some(1_700_086_400));
+
+    let capsule = IntentCapsule::from(IntentCapsuleV0::deserialize(&mut &data[8..]).unwrap());
+    assert!(!capsule.is_active);
+    assert_eq!(capsule.executed_at, Some(1_700_086_400));
+    // v0 did not record distribution, so distribute_assets must stay possible
+    assert_eq!(capsule.distributed_at, None);
+}
+
+#[test]
+fn current_layout_len_matches_serialized_size() {
+    let owner = Pubkey::new_unique();
+    let v0 = IntentCapsuleV0::deserialize(&mut &capsule_v0_bytes(&owner, 1, 1, &[b'x'; 1024], None)[8..]).unwrap();
+    let mut capsule = IntentCapsule::from(v0);
+    // The intent moves to IntentData on migration
+    capsule.intent_data.clear();
+    capsule.crank_task_id = Some(1);
+    capsule.executed_at = Some(1);
+    capsule.distributed_at = Some(1);
+    capsule.version = IntentCapsule::VERSION;
+
+    let mut data = Vec::new();
+    capsule.try_serialize(&mut data).unwrap();
+    assert_eq!(data.len(), 8 + IntentCapsule::LEN);
+}
+
+#[cfg(feature = "test-sbf")]
+mod svm {
+    use super::*;
+    use crate::common::*;
+    use heres_program::{CapsuleMigrated, CapsuleVault};
+    use solana_program_test::ProgramTest;
+    use solana_sdk::{account::Account, rent::Rent, signature::Signer};
+
+    /// Adds `owner`'s capsule in the v0 layout and returns its address and bump
+    fn add_v0_capsule(pt: &mut ProgramTest, owner: &Pubkey, intent: &[u8], executed_at: Option<i64>) -> (Pubkey, u8) {
+        let (capsule_key, bump) =
+            Pubkey::find_program_address(&[b"intent_capsule", owner.as_ref()], &heres_program::ID);
+        let data = capsule_v0_bytes(owner, bump, pda::vault(owner).1, intent, executed_at);
+        pt.add_account(
+            capsule_key,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: heres_program::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        (capsule_key, bump)
+    }
+
+    #[tokio::test]
+    async fn migrate_capsule_moves_v0_intent_out_and_preserves_fields() {
+        let mut pt = program_test();
+        let owner = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+        let intent = br#"{"intent":"v0","beneficiaries":[],"totalAmount":"1"}"#;
+        let (capsule_key, bump) = add_v0_capsule(&mut pt, &owner.pubkey(), intent, None);
+        let mut ctx = pt.start_with_context().await;
+        let payer = ctx.payer.pubkey();
+
+        let logs = process_with_logs(&mut ctx, &[instructions::migrate_capsule(&payer, &owner.pubkey())], &[])
+            .await
+            .unwrap();
+        let migrated_events: Vec<CapsuleMigrated> = events(&logs);
+        assert_eq!(migrated_events.len(), 1);
+        assert_eq!((migrated_events[0].capsule, migrated_events[0].owner), (capsule_key, owner.pubkey()));
+        assert_eq!(
+            (migrated_events[0].from_version, migrated_events[0].to_version),
+            (0, IntentCapsule::VERSION)
+        );
+
+        let account = ctx.banks_client.get_account(capsule_key).await.unwrap().unwrap();
+        assert_eq!(account.data.len(), 8 + IntentCapsule::LEN);
+        assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()));
+        // The v0 account was larger, so the freed rent went back to the owner
+        assert!(lamports(&mut ctx, &owner.pubkey()).await > LAMPORTS_PER_SOL);
+        let migrated = capsule(&mut ctx, &owner.pubkey()).await;
+        assert_eq!(migrated.version, IntentCapsule::VERSION);
+        assert!(migrated.intent_data.is_empty());
+        let stored = intent_data(&mut ctx, &owner.pubkey()).await;
+        assert_eq!((stored.capsule, stored.data), (capsule_key, intent.to_vec()));
+        assert_eq!((migrated.bump, migrated.retry_count), (bump, 2));
+        assert_eq!(migrated.rent_recipient, Pubkey::default());
+
+        // Running it twice is rejected
+        assert!(process(&mut ctx, &[instructions::migrate_capsule(&payer, &owner.pubkey())], &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn executed_v0_capsule_can_still_be_distributed_after_migration() {
+        let mut pt = program_test();
+        let owner = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+        let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+        let intent = intent_json("1", &[(heir, "100", "percentage")]);
+        add_v0_capsule(&mut pt, &owner.pubkey(), &intent, Some(1_700_086_400));
+        // Executed but never distributed: the vault still holds the deposit
+        let vault_data = [CapsuleVault::DISCRIMINATOR, &[0]].concat();
+        pt.add_account(
+            pda::vault(&owner.pubkey()).0,
+            Account {
+                lamports: Rent::default().minimum_balance(vault_data.len()) + LAMPORTS_PER_SOL,
+                data: vault_data,
+                owner: heres_program::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let mut ctx = pt.start_with_context().await;
+        let payer = ctx.payer.pubkey();
+        let fee_recipient = Pubkey::new_unique();
+        init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+        process(&mut ctx, &[instructions::migrate_capsule(&payer, &owner.pubkey())], &[]).await.unwrap();
+        let migrated = capsule(&mut ctx, &owner.pubkey()).await;
+        assert!(!migrated.is_active);
+        assert_eq!(migrated.executed_at, Some(1_700_086_400));
+        assert_eq!(migrated.distributed_at, None);
+        assert_eq!(migrated.locked_amount, 0);
+
+        let distribute = instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir], None);
+        process(&mut ctx, &[distribute], &[]).await.unwrap();
+        assert_eq!(lamports(&mut ctx, &heir).await, 2 * LAMPORTS_PER_SOL);
+        assert!(capsule(&mut ctx, &owner.pubkey()).await.distributed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn new_capsules_are_created_at_the_current_version() {
+        let mut pt = program_test();
+        let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+        let heir = Pubkey::new_unique();
+        let mut ctx = pt.start_with_context().await;
+        let fee_recipient = Pubkey::new_unique();
+        init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+        let intent = intent_json("1", &[(heir, "100", "percentage")]);
+        process(&mut ctx, &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)], &[&owner])
+            .await
+            .unwrap();
+        assert_eq!(capsule(&mut ctx, &owner.pubkey()).await.version, IntentCapsule::VERSION);
+
+        let payer = ctx.payer.pubkey();
+        assert!(process(&mut ctx, &[instructions::migrate_capsule(&payer, &owner.pubkey())], &[]).await.is_err());
+    }
+}
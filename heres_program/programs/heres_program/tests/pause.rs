@@ -0,0 +1,204 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use common::*;
+use heres_program::{ProgramConfigInitialized, SetPauseFlagsArgs, MAX_ALLOWED_VALIDATORS, TEE_VALIDATOR};
+use solana_sdk::signature::Signer;
+
+fn set_pause_flags(authority: &Pubkey, create: bool, execute: bool, distribute: bool) -> Instruction {
+    instructions::set_pause_flags(
+        authority,
+        SetPauseFlagsArgs {
+            create,
+            execute,
+            distribute,
+        },
+    )
+}
+
+#[tokio::test]
+async fn init_program_config_emits_its_authority() {
+    let mut ctx = program_test().start_with_context().await;
+    let admin = ctx.payer.pubkey();
+
+    let logs = process_with_logs(&mut ctx, &[instructions::init_program_config(&admin)], &[]).await.unwrap();
+    let initialized: Vec<ProgramConfigInitialized> = events(&logs);
+    assert_eq!(initialized.len(), 1);
+    assert_eq!(initialized[0].authority, admin);
+    assert!(!program_config(&mut ctx).await.pause_create);
+}
+
+#[tokio::test]
+async fn paused_operations_are_rejected_until_unpaused() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(&mut ctx, &[set_pause_flags(&admin, true, false, false)], &[]).await.unwrap();
+    assert!(process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent.clone())],
+        &[&owner],
+    )
+    .await
+    .is_err());
+
+    process(&mut ctx, &[set_pause_flags(&admin, false, true, true)], &[]).await.unwrap();
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    warp_forward(&mut ctx, 61).await;
+    assert!(process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.is_err());
+
+    process(&mut ctx, &[set_pause_flags(&admin, false, false, true)], &[]).await.unwrap();
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    let distribute = instructions::distribute_assets(&owner.pubkey(), &admin, &fee_recipient, None, &[heir], None);
+    assert!(process(&mut ctx, std::slice::from_ref(&distribute), &[]).await.is_err());
+
+    process(&mut ctx, &[set_pause_flags(&admin, false, false, false)], &[]).await.unwrap();
+    process(&mut ctx, &[distribute], &[]).await.unwrap();
+    assert_eq!(lamports(&mut ctx, &heir).await, 2 * LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn only_admin_can_set_pause_flags() {
+    let mut pt = program_test();
+    let outsider = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    assert!(process(
+        &mut ctx,
+        &[set_pause_flags(&outsider.pubkey(), true, true, true)],
+        &[&outsider],
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn owner_can_cancel_and_withdraw_while_everything_is_paused() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("3", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let balance_before_cancel = lamports(&mut ctx, &owner.pubkey()).await;
+    let vault_lamports = lamports(&mut ctx, &pda::vault(&owner.pubkey()).0).await;
+    let capsule_lamports = lamports(&mut ctx, &pda::capsule(&owner.pubkey()).0).await;
+
+    process(&mut ctx, &[set_pause_flags(&admin, true, true, true)], &[]).await.unwrap();
+    process(&mut ctx, &[instructions::cancel_capsule(&owner.pubkey(), None)], &[&owner]).await.unwrap();
+
+    assert_eq!(
+        lamports(&mut ctx, &owner.pubkey()).await,
+        balance_before_cancel + vault_lamports + capsule_lamports
+    );
+    assert!(ctx.banks_client.get_account(pda::capsule(&owner.pubkey()).0).await.unwrap().is_none());
+    assert!(ctx.banks_client.get_account(pda::vault(&owner.pubkey()).0).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn cancel_returns_spl_tokens_to_owner() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mint = add_mint(&mut pt, &owner.pubkey());
+    let owner_ata = add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("2", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, Some(&mint), 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(token_balance(&mut ctx, &owner_ata).await, 3 * LAMPORTS_PER_SOL);
+
+    process(&mut ctx, &[instructions::cancel_capsule(&owner.pubkey(), Some(&mint))], &[&owner]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &owner_ata).await, 5 * LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn executed_capsule_cannot_be_cancelled() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+
+    assert!(process(&mut ctx, &[instructions::cancel_capsule(&owner.pubkey(), None)], &[&owner])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn admin_manages_validator_allowlist() {
+    let mut pt = program_test();
+    let outsider = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let config = program_config(&mut ctx).await;
+    assert!(config.allowed_validators.is_empty());
+    assert!(config.is_validator_allowed(&TEE_VALIDATOR));
+
+    let validator = Pubkey::new_unique();
+    process(&mut ctx, &[instructions::set_allowed_validators(&admin, vec![validator])], &[]).await.unwrap();
+    let config = program_config(&mut ctx).await;
+    assert_eq!(config.allowed_validators, vec![validator]);
+    assert!(!config.is_validator_allowed(&TEE_VALIDATOR));
+
+    assert!(process(
+        &mut ctx,
+        &[instructions::set_allowed_validators(&outsider.pubkey(), vec![])],
+        &[&outsider],
+    )
+    .await
+    .is_err());
+    let too_many = (0..=MAX_ALLOWED_VALIDATORS).map(|_| Pubkey::new_unique()).collect();
+    assert!(process(&mut ctx, &[instructions::set_allowed_validators(&admin, too_many)], &[]).await.is_err());
+}
@@ -0,0 +1,195 @@
+#![cfg(feature = "test-sbf")]
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+const CREATION_FEE: u64 = 10_000_000;
+
+async fn run_cycle(
+    ctx: &mut ProgramTestContext,
+    owner: &Keypair,
+    fee_recipient: &Pubkey,
+    mint: Option<&Pubkey>,
+    heir: &Pubkey,
+) {
+    warp_forward(ctx, 61).await;
+    let payer = ctx.payer.pubkey();
+    process(
+        ctx,
+        &[
+            instructions::execute_intent(&owner.pubkey(), None),
+            instructions::distribute_assets(&owner.pubkey(), &payer, fee_recipient, mint, &[*heir], None),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn recreate_switches_from_sol_to_spl_and_charges_creation_fee() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mint = add_mint(&mut pt, &owner.pubkey());
+    let owner_ata = add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+    let heir_ata = add_token_account(&mut pt, &mint, &heir, 0);
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, CREATION_FEE, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), Some(&fee_recipient), None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    run_cycle(&mut ctx, &owner, &fee_recipient, None, &heir).await;
+
+    let intent = intent_json("2", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::recreate_capsule(&owner.pubkey(), Some(&fee_recipient), None, Some(&mint), 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(lamports(&mut ctx, &fee_recipient).await, LAMPORTS_PER_SOL + 2 * CREATION_FEE);
+    assert_eq!(token_balance(&mut ctx, &owner_ata).await, 3 * LAMPORTS_PER_SOL);
+
+    let state = capsule(&mut ctx, &owner.pubkey()).await;
+    assert_eq!(state.mint, mint);
+    assert!(state.is_active);
+    assert!(state.distributed_at.is_none());
+
+    run_cycle(&mut ctx, &owner, &fee_recipient, Some(&mint), &heir).await;
+    assert_eq!(token_balance(&mut ctx, &heir_ata).await, 2 * LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn recreate_resets_mint_and_closes_previous_vault_token_account() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mint = add_mint(&mut pt, &owner.pubkey());
+    let owner_ata = add_token_account(&mut pt, &mint, &owner.pubkey(), 5 * LAMPORTS_PER_SOL);
+    add_token_account(&mut pt, &mint, &heir, 0);
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("2", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, Some(&mint), 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    run_cycle(&mut ctx, &owner, &fee_recipient, Some(&mint), &heir).await;
+
+    // Tokens sent to the vault ATA after distribution are refunded on recreate
+    let vault_ata = get_associated_token_address(&pda::vault(&owner.pubkey()).0, &mint);
+    let owner_sol_before = lamports(&mut ctx, &owner.pubkey()).await;
+    let stray = spl_token::instruction::transfer(
+        &spl_token::ID,
+        &owner_ata,
+        &vault_ata,
+        &owner.pubkey(),
+        &[],
+        LAMPORTS_PER_SOL,
+    )
+    .unwrap();
+    process(&mut ctx, &[stray], &[&owner]).await.unwrap();
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::recreate_capsule(&owner.pubkey(), None, Some(&mint), None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(token_balance(&mut ctx, &owner_ata).await, 3 * LAMPORTS_PER_SOL);
+    assert!(ctx.banks_client.get_account(vault_ata).await.unwrap().is_none());
+    let state = capsule(&mut ctx, &owner.pubkey()).await;
+    assert_eq!(state.mint, Pubkey::default());
+    // Owner paid 1 SOL into the vault and got the closed ATA's rent back
+    assert!(lamports(&mut ctx, &owner.pubkey()).await > owner_sol_before - LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn recreate_returns_leftover_sol_to_owner() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    run_cycle(&mut ctx, &owner, &fee_recipient, None, &heir).await;
+
+    let vault = pda::vault(&owner.pubkey()).0;
+    let vault_rent = lamports(&mut ctx, &vault).await;
+    let stray = solana_sdk::system_instruction::transfer(&owner.pubkey(), &vault, LAMPORTS_PER_SOL);
+    process(&mut ctx, &[stray], &[&owner]).await.unwrap();
+    let owner_before = lamports(&mut ctx, &owner.pubkey()).await;
+
+    let intent = intent_json("2", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::recreate_capsule(&owner.pubkey(), None, None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(lamports(&mut ctx, &vault).await, vault_rent + 2 * LAMPORTS_PER_SOL);
+    assert_eq!(lamports(&mut ctx, &owner.pubkey()).await, owner_before - LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn recreate_requires_previous_cycle_to_be_distributed() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+
+    let intent = intent_json("1", &[(heir, "100", "percentage")]);
+    process(
+        &mut ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent.clone())],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+
+    assert!(process(
+        &mut ctx,
+        &[instructions::recreate_capsule(&owner.pubkey(), None, None, None, 60, intent)],
+        &[&owner],
+    )
+    .await
+    .is_err());
+}
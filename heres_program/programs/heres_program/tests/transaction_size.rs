@@ -0,0 +1,63 @@
+//! distribute_assets takes one remaining account per beneficiary, so the intent size is bounded by how many
+//! accounts fit in a 1232-byte transaction. These build the real instruction for the worst case (an SPL
+//! capsule with an executor bounty, plus compute-budget instructions) and check the serialized size.
+
+use heres_client::{instructions, LEGACY_MAX_BENEFICIARIES, MAX_BENEFICIARIES};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, AddressLookupTableAccount, Message, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, VersionedTransaction},
+};
+
+fn distribute(payer: &Pubkey, beneficiaries: usize) -> Vec<Instruction> {
+    let heirs: Vec<Pubkey> = (0..beneficiaries).map(|_| Pubkey::new_unique()).collect();
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(1_400_000),
+        ComputeBudgetInstruction::set_compute_unit_price(1_000),
+        instructions::distribute_assets(
+            &Pubkey::new_unique(),
+            payer,
+            &Pubkey::new_unique(),
+            Some(&Pubkey::new_unique()),
+            &heirs,
+            Some(&Pubkey::new_unique()),
+        ),
+    ]
+}
+
+fn legacy_size(beneficiaries: usize) -> usize {
+    let payer = Keypair::new();
+    let message = Message::new(&distribute(&payer.pubkey(), beneficiaries), Some(&payer.pubkey()));
+    let tx = Transaction::new(&[&payer], message, Hash::default());
+    bincode::serialize(&tx).unwrap().len()
+}
+
+#[test]
+fn legacy_transaction_fits_up_to_the_legacy_limit() {
+    assert!(legacy_size(LEGACY_MAX_BENEFICIARIES) <= PACKET_DATA_SIZE);
+    assert!(legacy_size(LEGACY_MAX_BENEFICIARIES + 1) > PACKET_DATA_SIZE);
+}
+
+#[test]
+fn full_intent_fits_a_v0_transaction_with_a_lookup_table() {
+    let payer = Keypair::new();
+    let instructions = distribute(&payer.pubkey(), MAX_BENEFICIARIES);
+    // Everything but the fee payer and the invoked programs can come from the table
+    let table = AddressLookupTableAccount {
+        key: Pubkey::new_unique(),
+        addresses: instructions
+            .iter()
+            .flat_map(|ix| ix.accounts.iter().map(|meta| meta.pubkey))
+            .filter(|key| *key != payer.pubkey())
+            .collect(),
+    };
+    let message = v0::Message::try_compile(&payer.pubkey(), &instructions, &[table], Hash::default()).unwrap();
+    let tx = VersionedTransaction::try_new(VersionedMessage::V0(message), &[&payer]).unwrap();
+    let size = bincode::serialize(&tx).unwrap().len();
+    assert!(size <= PACKET_DATA_SIZE, "{MAX_BENEFICIARIES} beneficiaries serialize to {size} bytes");
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "test-sbf")]
+//! Callers other than the owner or the fee authority are turned away, and a capsule cannot be paid out early,
+//! partially or twice.
+
+mod common;
+
+use anchor_lang::error::ErrorCode as AnchorError;
+use common::*;
+use heres_program::ErrorCode;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_program_test::ProgramTestContext;
+
+async fn active_capsule(ctx: &mut ProgramTestContext, owner: &Keypair, heirs: &[(solana_sdk::pubkey::Pubkey, &str)]) {
+    let beneficiaries: Vec<_> = heirs.iter().map(|(h, share)| (*h, *share, "percentage")).collect();
+    process(
+        ctx,
+        &[instructions::create_capsule(&owner.pubkey(), None, None, 60, intent_json("1", &beneficiaries))],
+        &[owner],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn strangers_cannot_change_someone_elses_capsule() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let stranger = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+    active_capsule(&mut ctx, &owner, &[(heir, "100")]).await;
+    let before = capsule(&mut ctx, &owner.pubkey()).await;
+    let intent_before = intent_data(&mut ctx, &owner.pubkey()).await.data;
+
+    // Owner-seeded accounts: the stranger's key derives a different capsule address
+    let hijack = intent_json("1", &[(stranger.pubkey(), "100", "percentage")]);
+    let (owner_key, stranger_key) = (owner.pubkey(), stranger.pubkey());
+    for ix in [
+        instructions::update_intent(&owner_key, hijack),
+        instructions::set_rent_recipient(&owner_key, &stranger_key),
+        instructions::update_activity(&owner_key, false),
+        instructions::cancel_capsule(&owner_key, None),
+    ] {
+        let ix = signed_by(ix, &owner_key, &stranger_key);
+        assert_error(process(&mut ctx, &[ix], &[&stranger]).await, AnchorError::ConstraintSeeds);
+    }
+
+    let after = capsule(&mut ctx, &owner_key).await;
+    assert!(after.is_active);
+    assert_eq!(after.last_activity, before.last_activity);
+    assert_eq!(after.retry_count, before.retry_count);
+    assert_eq!(after.designated_rent_recipient(), None);
+    assert_eq!(intent_data(&mut ctx, &owner_key).await.data, intent_before);
+}
+
+#[tokio::test]
+async fn only_the_fee_authority_changes_fees() {
+    let mut pt = program_test();
+    let stranger = add_wallet(&mut pt, LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let admin = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 10_000_000, 300).await;
+
+    assert_error(
+        process(&mut ctx, &[instructions::update_fee_config(&stranger.pubkey(), 0, 0)], &[&stranger]).await,
+        ErrorCode::Unauthorized,
+    );
+    assert_error(
+        process(
+            &mut ctx,
+            &[instructions::set_keeper_bounty(&stranger.pubkey(), LAMPORTS_PER_SOL, 0)],
+            &[&stranger],
+        )
+        .await,
+        ErrorCode::Unauthorized,
+    );
+    let config = fee_config(&mut ctx).await;
+    assert_eq!(config.creation_fee_lamports, 10_000_000);
+    assert_eq!(config.execution_fee_bps, 300);
+    assert_eq!(config.keeper_bounty_lamports, 0);
+
+    process(&mut ctx, &[instructions::update_fee_config(&admin, 0, 100)], &[]).await.unwrap();
+    assert_eq!(fee_config(&mut ctx).await.execution_fee_bps, 100);
+}
+
+#[tokio::test]
+async fn capsule_pays_out_only_after_execution_and_to_every_beneficiary_at_once() {
+    let mut pt = program_test();
+    let owner = add_wallet(&mut pt, 10 * LAMPORTS_PER_SOL);
+    let fee_recipient = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_a = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let heir_b = add_wallet(&mut pt, LAMPORTS_PER_SOL).pubkey();
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    init_configs(&mut ctx, &fee_recipient, 0, 0).await;
+    active_capsule(&mut ctx, &owner, &[(heir_a, "50"), (heir_b, "50")]).await;
+
+    let heirs = [heir_a, heir_b];
+    let distribute = instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &heirs, None);
+    assert_error(
+        process(&mut ctx, std::slice::from_ref(&distribute), &[]).await,
+        ErrorCode::CapsuleActive,
+    );
+    assert_error(
+        process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await,
+        ErrorCode::InactivityPeriodNotMet,
+    );
+
+    warp_forward(&mut ctx, 61).await;
+    process(&mut ctx, &[instructions::execute_intent(&owner.pubkey(), None)], &[]).await.unwrap();
+    // Dropping a beneficiary's account cannot be used to pay the others (or nobody) instead
+    let partial = instructions::distribute_assets(&owner.pubkey(), &payer, &fee_recipient, None, &[heir_a], None);
+    assert_error(process(&mut ctx, &[partial], &[]).await, ErrorCode::MissingBeneficiaryAccount);
+    assert_eq!(lamports(&mut ctx, &heir_a).await, LAMPORTS_PER_SOL);
+    assert!(capsule(&mut ctx, &owner.pubkey()).await.distributed_at.is_none());
+
+    process(&mut ctx, std::slice::from_ref(&distribute), &[]).await.unwrap();
+    assert_error(process(&mut ctx, &[distribute], &[]).await, ErrorCode::AlreadyDistributed);
+    assert_eq!(lamports(&mut ctx, &heir_a).await, LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 2);
+    assert_eq!(lamports(&mut ctx, &heir_b).await, LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 2);
+}
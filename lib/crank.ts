@@ -5,8 +5,14 @@ import type { Wallet } from '@coral-xyz/anchor'
 const WalletClass = (require('@coral-xyz/anchor').Wallet || (AnchorProvider.prototype as any).wallet)
 import idl from '../idl/HeresProgram.json'
 import { getSolanaConnection, getProgramId } from '@/config/solana'
-import { getCapsulePDA, getCapsuleVaultPDA, getFeeConfigPDA } from './program'
-import { SOLANA_CONFIG } from '@/constants'
+import {
+  getCapsulePDA,
+  getCapsuleVaultPDA,
+  getPermissionPDA,
+  getProgramConfigPDA,
+  fetchIntentData,
+} from './program'
+import { MAGICBLOCK_ER } from '@/constants'
 
 const TOKEN_PROGRAM_ID = new PublicKey('TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA')
 const SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID = new PublicKey('ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL')
@@ -84,7 +90,9 @@ export async function executeCapsuleIntent(
   const provider = new AnchorProvider(connection, wallet, { commitment: 'confirmed' })
   const program = new Program(idl as any, provider)
 
-  const beneficiaries = parseBeneficiaries(capsule.account.intentData)
+  // The intent lives in the capsule's IntentData account; the inline field is only set on unmigrated capsules
+  const intentData = await fetchIntentData(connection, capsule.publicKey, capsule.account.intentData)
+  const beneficiaries = parseBeneficiaries(intentData)
   if (beneficiaries.length === 0) throw new Error('No beneficiaries in intent data')
 
   const mint = capsule.account.mint
@@ -92,15 +100,9 @@ export async function executeCapsuleIntent(
 
   const [capsulePDA] = getCapsulePDA(capsule.account.owner)
   const [vaultPDA] = getCapsuleVaultPDA(capsule.account.owner)
-  const [feeConfigPDA] = getFeeConfigPDA()
-  const platformFeeRecipient = new PublicKey(
-    SOLANA_CONFIG.PLATFORM_FEE_RECIPIENT || 'Covn3moA8qstPgXPgueRGMSmi94yXvuDCWTjQVBxHpzb'
-  )
-
-  let vaultTokenAccount = null
-  if (isSpl) {
-    vaultTokenAccount = getAssociatedTokenAddress(mint, vaultPDA)
-  }
+  const [programConfigPDA] = getProgramConfigPDA()
+  const permissionProgramId = new PublicKey(MAGICBLOCK_ER.PERMISSION_PROGRAM_ID)
+  const [permissionPDA] = getPermissionPDA(capsulePDA, permissionProgramId)
 
   const remainingAccounts = beneficiaries.map((b) => {
     const beneficiaryOwner = new PublicKey(b.address)
@@ -126,11 +128,11 @@ export async function executeCapsuleIntent(
     .accounts({
       capsule: capsulePDA,
       vault: vaultPDA,
-      systemProgram: SystemProgram.programId,
-      tokenProgram: TOKEN_PROGRAM_ID,
-      feeConfig: feeConfigPDA,
-      platformFeeRecipient: platformFeeRecipient,
-      vaultTokenAccount: vaultTokenAccount as any,
+      permissionProgram: permissionProgramId,
+      permission: permissionPDA,
+      programConfig: programConfigPDA,
+      // Signing as keeper records the crank as executor, so it is paid the keeper bounty on distribution
+      keeper: crankKeypair.publicKey,
     } as any)
     .remainingAccounts(remainingAccounts)
     .rpc()
@@ -2,7 +2,7 @@
  * Solana program utilities
  */
 
-import { PublicKey } from '@solana/web3.js'
+import { Connection, PublicKey } from '@solana/web3.js'
 import { getProgramId } from '@/config/solana'
 
 /**
@@ -15,6 +15,26 @@ export function getCapsulePDA(owner: PublicKey): [PublicKey, number] {
   )
 }
 
+/**
+ * Derive intent data PDA (intent JSON stored next to the capsule, seeds = ["intent_data", capsule])
+ */
+export function getIntentDataPDA(capsule: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('intent_data'), capsule.toBuffer()],
+    getProgramId()
+  )
+}
+
+/**
+ * Derive program config PDA (admin pause flags and validator allowlist, seeds = ["program_config"])
+ */
+export function getProgramConfigPDA(): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('program_config')],
+    getProgramId()
+  )
+}
+
 /**
  * Derive fee config PDA (platform fee config, seeds = ["fee_config"])
  */
@@ -75,3 +95,21 @@ export function getPermissionPDA(pda: PublicKey, permissionProgramId: PublicKey)
     permissionProgramId
   )
 }
+
+/**
+ * Read a capsule's intent from its IntentData account (always on the base layer, never delegated).
+ * Capsules not yet run through migrate_capsule still keep the intent inline; it is returned as the fallback.
+ */
+export async function fetchIntentData(
+  connection: Connection,
+  capsule: PublicKey,
+  inlineIntentData: Uint8Array
+): Promise<Uint8Array> {
+  const [intentDataPDA] = getIntentDataPDA(capsule)
+  const accountInfo = await connection.getAccountInfo(intentDataPDA)
+  // discriminator (8) + capsule (32) + bump (1) + data: Vec<u8> (4-byte length + bytes)
+  const offset = 8 + 32 + 1
+  if (!accountInfo || accountInfo.data.length < offset + 4) return inlineIntentData
+  const length = accountInfo.data.readUInt32LE(offset)
+  return new Uint8Array(accountInfo.data.subarray(offset + 4, offset + 4 + length))
+}
@@ -13,6 +13,9 @@ import {
   getCapsulePDA,
   getFeeConfigPDA,
   getCapsuleVaultPDA,
+  getIntentDataPDA,
+  getProgramConfigPDA,
+  fetchIntentData,
   getBufferPDA,
   getDelegationRecordPDA,
   getDelegationMetadataPDA,
@@ -121,7 +124,9 @@ export async function createCapsule(
 
   const [capsulePDA] = getCapsulePDA(wallet.publicKey!)
   const [vaultPDA] = getCapsuleVaultPDA(wallet.publicKey!)
+  const [intentDataPDA] = getIntentDataPDA(capsulePDA)
   const [feeConfigPDA] = getFeeConfigPDA()
+  const [programConfigPDA] = getProgramConfigPDA()
 
   const platformFeeRecipient = SOLANA_CONFIG.PLATFORM_FEE_RECIPIENT
     ? new PublicKey(SOLANA_CONFIG.PLATFORM_FEE_RECIPIENT)
@@ -146,6 +151,7 @@ export async function createCapsule(
       const accounts: any = {
         capsule: capsulePDA,
         vault: vaultPDA,
+        intentData: intentDataPDA,
         owner: wallet.publicKey!,
         feeConfig: feeConfigPDA,
         platformFeeRecipient: platformFeeRecipient || null,
@@ -155,6 +161,7 @@ export async function createCapsule(
         sourceTokenAccount: mint ? getAssociatedTokenAddress(mint, wallet.publicKey!) : null,
         vaultTokenAccount: mint ? getAssociatedTokenAddress(mint, vaultPDA) : null,
         associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        programConfig: programConfigPDA,
       }
 
       console.log('[createCapsule] Accounts:', Object.keys(accounts).map(k => `${k}: ${accounts[k]?.toString()}`))
@@ -224,7 +231,9 @@ export async function updateIntent(
     .updateIntent(intentDataBuffer)
     .accounts({
       capsule: capsulePDA,
+      intentData: getIntentDataPDA(capsulePDA)[0],
       owner: wallet.publicKey!,
+      systemProgram: SystemProgram.programId,
     })
     .rpc()
 
@@ -259,6 +268,7 @@ export async function executeIntent(
     vault: vaultPDA,
     permissionProgram: permissionProgramId,
     permission: permissionPDA,
+    programConfig: getProgramConfigPDA()[0],
   }
 
   const remainingAccounts = beneficiaries?.map(b => {
@@ -293,7 +303,8 @@ export async function executeIntent(
  */
 export async function delegateCapsule(
   wallet: WalletContextState,
-  validatorPubkey?: PublicKey
+  validatorPubkey?: PublicKey,
+  commitFrequencyMs = 0
 ): Promise<string> {
   const program = getProgram(wallet)
   if (!program) throw new Error('Wallet not connected')
@@ -335,36 +346,38 @@ export async function delegateCapsule(
 
   const [vaultPDA] = getCapsuleVaultPDA(wallet.publicKey)
 
-  // Derive PDAs for Capsule delegation (Correct Owners: magicProgramId for buffer, delegationProgramId for others)
-  const [bufferPDA] = getBufferPDA(capsulePDA, magicProgramId)
+  // Derive PDAs for Capsule delegation (buffers are owned by this program, records and metadata by the delegation program)
+  const [bufferPDA] = getBufferPDA(capsulePDA, getProgramId())
   const [delegationRecordPDA] = getDelegationRecordPDA(capsulePDA, delegationProgramId)
   const [delegationMetadataPDA] = getDelegationMetadataPDA(capsulePDA, delegationProgramId)
 
   // Derive PDAs for Vault delegation
-  const [vaultBufferPDA] = getBufferPDA(vaultPDA, magicProgramId)
+  const [vaultBufferPDA] = getBufferPDA(vaultPDA, getProgramId())
   const [vaultDelegationRecordPDA] = getDelegationRecordPDA(vaultPDA, delegationProgramId)
   const [vaultDelegationMetadataPDA] = getDelegationMetadataPDA(vaultPDA, delegationProgramId)
 
   const accounts = {
     payer: wallet.publicKey,
     owner: wallet.publicKey,
-    validator: validatorPubkey ?? null,
+    bufferPda: bufferPDA,
+    delegationRecordPda: delegationRecordPDA,
+    delegationMetadataPda: delegationMetadataPDA,
     pda: capsulePDA,
-    pdaBuffer: bufferPDA,
-    pdaDelegationRecord: delegationRecordPDA,
-    pdaDelegationMetadata: delegationMetadataPDA,
+    bufferVault: vaultBufferPDA,
+    delegationRecordVault: vaultDelegationRecordPDA,
+    delegationMetadataVault: vaultDelegationMetadataPDA,
     vault: vaultPDA,
-    vaultBuffer: vaultBufferPDA,
-    vaultDelegationRecord: vaultDelegationRecordPDA,
-    vaultDelegationMetadata: vaultDelegationMetadataPDA,
     // Programs at the end
     magicProgram: magicProgramId,
     delegationProgram: delegationProgramId,
     systemProgram: SystemProgram.programId,
+    programConfig: getProgramConfigPDA()[0],
+    ownerProgram: getProgramId(),
   }
 
+  // The validator is an instruction argument; the program falls back to the TEE validator when it is null
   const tx = await program.methods
-    .delegateCapsule()
+    .delegateCapsule({ commitFrequencyMs, validator: validatorPubkey ?? null })
     // @ts-ignore
     .accounts(accounts)
     .rpc()
@@ -381,7 +394,7 @@ export async function delegateCapsule(
 export async function scheduleExecuteIntent(
   wallet: WalletContextState,
   ownerPublicKey: PublicKey,
-  args?: { taskId?: BN; executionIntervalMillis?: BN; iterations?: BN },
+  args?: { executionIntervalMillis?: BN; iterations?: BN },
   token?: string
 ): Promise<string> {
   if (!wallet.publicKey) throw new Error('Wallet not connected')
@@ -402,14 +415,14 @@ export async function scheduleExecuteIntent(
     vault: vaultPDA,
     permissionProgram: permissionProgramId,
     permission: permissionPDA,
+    programConfig: getProgramConfigPDA()[0],
   }
 
   console.log('[scheduleExecuteIntent] Scheduling on TEE RPC')
   console.log(' - Capsule:', capsulePDA.toBase58())
   console.log(' - Payer:', wallet.publicKey.toBase58())
 
-  // Default values for optional args
-  const taskId = args?.taskId ?? new BN(Date.now());
+  // Default values for optional args; the program derives the task id from the capsule
   const executionIntervalMillis = args?.executionIntervalMillis ?? new BN(MAGICBLOCK_ER.CRANK_DEFAULT_INTERVAL_MS || 60000);
   const iterations = args?.iterations ?? new BN(MAGICBLOCK_ER.CRANK_DEFAULT_ITERATIONS || 0);
 
@@ -433,9 +446,8 @@ export async function scheduleExecuteIntent(
     // Use transaction() instead of rpc() to avoid blockhash issues as suggested
     const tx = await teeProgram.methods
       .scheduleExecuteIntent({
-        task_id: taskId,
-        execution_interval_millis: executionIntervalMillis,
-        iterations: iterations,
+        executionIntervalMillis,
+        iterations,
       })
       // @ts-ignore
       .accounts(accounts)
@@ -515,15 +527,28 @@ export async function distributeAssets(
     ? new PublicKey(SOLANA_CONFIG.PLATFORM_FEE_RECIPIENT)
     : null
 
+  // The keeper that executed the capsule (executed_by) is owed the executor bounty and must be passed
+  const capsuleAccount: any = await (program.account as any).intentCapsule.fetch(capsulePDA)
+  const executedBy: PublicKey = capsuleAccount.executedBy
+  const executor = executedBy && !executedBy.equals(PublicKey.default) ? executedBy : null
+
   const accounts: any = {
     capsule: capsulePDA,
     vault: vaultPDA,
+    intentData: getIntentDataPDA(capsulePDA)[0],
     systemProgram: SystemProgram.programId,
     tokenProgram: TOKEN_PROGRAM_ID,
     feeConfig: feeConfigPDA,
     platformFeeRecipient: platformFeeRecipient || null,
     mint: mint || null,
     vaultTokenAccount: mint ? getAssociatedTokenAddress(mint, vaultPDA) : null,
+    platformFeeTokenAccount: mint && platformFeeRecipient ? getAssociatedTokenAddress(mint, platformFeeRecipient) : null,
+    keeperTokenAccount: mint ? getAssociatedTokenAddress(mint, wallet.publicKey!) : null,
+    executor,
+    executorTokenAccount: mint && executor ? getAssociatedTokenAddress(mint, executor) : null,
+    payer: wallet.publicKey!,
+    associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+    programConfig: getProgramConfigPDA()[0],
   }
 
   const remainingAccounts = beneficiaries?.map(b => {
@@ -678,7 +703,19 @@ export async function recreateCapsule(
 
   const [capsulePDA] = getCapsulePDA(wallet.publicKey!)
   const [vaultPDA] = getCapsuleVaultPDA(wallet.publicKey!)
+  const [intentDataPDA] = getIntentDataPDA(capsulePDA)
   const [feeConfigPDA] = getFeeConfigPDA()
+  const [programConfigPDA] = getProgramConfigPDA()
+  const platformFeeRecipient = SOLANA_CONFIG.PLATFORM_FEE_RECIPIENT
+    ? new PublicKey(SOLANA_CONFIG.PLATFORM_FEE_RECIPIENT)
+    : null
+
+  // Leftover tokens of the previous cycle are refunded; its accounts are only needed when the mint changes
+  const previousMint = (await getCapsule(wallet.publicKey!))?.mint
+  const replacedMint =
+    previousMint && !previousMint.equals(PublicKey.default) && !(mint && mint.equals(previousMint))
+      ? previousMint
+      : null
 
   // Convert Uint8Array to Buffer for Anchor (required by Blob.encode)
   let intentDataBuffer: Buffer | number[]
@@ -692,23 +729,35 @@ export async function recreateCapsule(
   const accounts: {
     capsule: PublicKey
     vault: PublicKey
+    intentData: PublicKey
     owner: PublicKey
-    systemProgram: PublicKey
     feeConfig: PublicKey
+    platformFeeRecipient: PublicKey | null
+    systemProgram: PublicKey
     tokenProgram: PublicKey
     mint: PublicKey | null
     sourceTokenAccount: PublicKey | null
     vaultTokenAccount: PublicKey | null
+    previousVaultTokenAccount: PublicKey | null
+    previousOwnerTokenAccount: PublicKey | null
+    associatedTokenProgram: PublicKey
+    programConfig: PublicKey
   } = {
     capsule: capsulePDA,
     vault: vaultPDA,
+    intentData: intentDataPDA,
     owner: wallet.publicKey!,
-    systemProgram: SystemProgram.programId,
     feeConfig: feeConfigPDA,
+    platformFeeRecipient,
+    systemProgram: SystemProgram.programId,
     tokenProgram: TOKEN_PROGRAM_ID,
     mint: null,
     sourceTokenAccount: null,
     vaultTokenAccount: null,
+    previousVaultTokenAccount: null,
+    previousOwnerTokenAccount: null,
+    associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+    programConfig: programConfigPDA,
   }
 
   if (mint) {
@@ -716,6 +765,10 @@ export async function recreateCapsule(
     accounts.sourceTokenAccount = getAssociatedTokenAddress(mint, wallet.publicKey!)
     accounts.vaultTokenAccount = getAssociatedTokenAddress(mint, vaultPDA)
   }
+  if (replacedMint) {
+    accounts.previousVaultTokenAccount = getAssociatedTokenAddress(replacedMint, vaultPDA)
+    accounts.previousOwnerTokenAccount = getAssociatedTokenAddress(replacedMint, wallet.publicKey!)
+  }
 
   const tx = await program.methods
     .recreateCapsule(new BN(inactivityPeriodSeconds), intentDataBuffer)
@@ -829,7 +882,7 @@ export async function getCapsule(owner: PublicKey): Promise<IntentCapsule | null
     const lastActivity = readI64(dataToParse, offset)
     offset += 8
 
-    // intent_data: Vec<u8> (4 bytes length + data)
+    // intent_data: Vec<u8> (4 bytes length + data); empty once the intent lives in the IntentData account
     const intentDataLength = readU32(dataToParse, offset)
     offset += 4
     const intentDataBytes = dataToParse.slice(offset, offset + intentDataLength)
@@ -852,7 +905,7 @@ export async function getCapsule(owner: PublicKey): Promise<IntentCapsule | null
       owner: ownerPubkey,
       inactivityPeriod: Number(inactivityPeriod),
       lastActivity: Number(lastActivity),
-      intentData: new Uint8Array(intentDataBytes),
+      intentData: await fetchIntentData(connection, capsulePDA, new Uint8Array(intentDataBytes)),
       isActive,
       executedAt,
       accountOwner: accountInfo.owner,
@@ -927,6 +980,7 @@ export async function getCapsuleByAddress(capsulePda: PublicKey): Promise<(Inten
     offset += 8
     const lastActivity = Number(readI64(dataToParse, offset))
     offset += 8
+    // Inline intent_data is empty once the intent lives in the IntentData account
     const intentDataLength = readU32(dataToParse, offset)
     offset += 4
     const intentDataBytes = dataToParse.slice(offset, offset + intentDataLength)
@@ -945,7 +999,7 @@ export async function getCapsuleByAddress(capsulePda: PublicKey): Promise<(Inten
       owner: ownerPubkey,
       inactivityPeriod,
       lastActivity,
-      intentData: new Uint8Array(intentDataBytes),
+      intentData: await fetchIntentData(connection, capsulePda, new Uint8Array(intentDataBytes)),
       isActive,
       executedAt,
       capsuleAddress: capsulePda.toBase58(),